@@ -17,14 +17,25 @@ along with this program; if not, see
 */
 
 use chrono::{prelude::*, DateTime};
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::cmp::Ordering;
 use serde::{Deserialize, Serialize};
 
-#[derive(Hash, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Assignment {
-	pub due_date: DateTime<Local>,
+	// None for someday items
+	pub due_date: Option<DateTime<Local>>,
 	pub name: String,
+	// set if this assignment is managed by a provider script
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub source: Option<ExternalSource>,
+	// lowercase words for filtering on, like "exam"
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub tags: Vec<String>,
+	// stays the same through edits, unlike the uid, so merging can tell an edit from a new assignment
+	// 0 until the list gives it one, lists from before ids existed get them when loaded
+	#[serde(default)]
+	pub id: u64,
 }
 
 impl Clone for Assignment {
@@ -32,37 +43,85 @@ impl Clone for Assignment {
 		Self {
 			due_date: self.due_date.clone(),
 			name: self.name.clone(),
+			source: self.source.clone(),
+			tags: self.tags.clone(),
+			id: self.id,
+		}
+	}
+}
+
+// hashes the same as before due dates were optional and tags existed, so older assignments keep their uids
+// the id is left out, the uid is about what the assignment says
+impl Hash for Assignment {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		if let Some(due_date) = &self.due_date {
+			due_date.hash(state);
+		}
+		self.name.hash(state);
+		self.source.hash(state);
+		if !self.tags.is_empty() {
+			self.tags.hash(state);
 		}
 	}
 }
 
+// so is equality, two copies of an assignment are equal whatever their ids
 impl PartialEq for Assignment {
 	fn eq(&self, other: &Self) -> bool {
 		self.due_date == other.due_date &&
-			self.name == other.name
+			self.name == other.name &&
+			self.source == other.source &&
+			self.tags == other.tags
 	}
 }
 
 impl Eq for Assignment {
 }
 
+// assignments without a due date go after every one with one
 impl Ord for Assignment {
 	fn cmp(&self, other: &Self) -> Ordering {
-		if self.due_date == other.due_date {
-			self.name.cmp(&other.name)
-		}
-		else {
-			self.due_date.cmp(&other.due_date)
+		match (&self.due_date, &other.due_date) {
+			(Some(a), Some(b)) if a != b => a.cmp(b),
+			(Some(_), None) => Ordering::Less,
+			(None, Some(_)) => Ordering::Greater,
+			_ => self.name.cmp(&other.name),
 		}
 	}
 }
 
+impl Assignment {
+	// splits what was typed into tags, on spaces and commas
+	pub fn parse_tags(tags: &str) -> Vec<String> {
+		let mut tags = tags.split([' ', ','])
+			.filter(|tag| !tag.is_empty())
+			.map(|tag| tag.to_lowercase())
+			.collect::<Vec<String>>();
+		tags.sort();
+		tags.dedup();
+		tags
+	}
+
+	// uncompleted ones that are still around past this are overdue
+	pub fn is_past(&self, now: DateTime<Local>) -> bool {
+		self.due_date.is_some_and(|due_date| due_date < now)
+	}
+}
+
 impl PartialOrd for Assignment {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
 		Some(self.cmp(other))
 	}
 }
 
+// where an externally managed assignment came from
+// script is the file name of the provider script, id is the id the script gave it
+#[derive(Hash, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalSource {
+	pub script: String,
+	pub id: String,
+}
+
 #[derive(Hash, Serialize, Deserialize)]
 pub struct AssignmentV1 {
 	pub due_date: DateTime<Local>,
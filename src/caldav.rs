@@ -0,0 +1,827 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use log;
+
+use crate::assignment::{Assignment, ExternalSource};
+use crate::config::{config, CaldavConfig, CaldavPrefer};
+use crate::todolist::TodoList;
+
+// what marks an assignment as coming from the CalDAV server, in place of a script name
+pub const CALDAV_SOURCE: &str = "CalDAV";
+
+const CALDAV_NS: &str = "urn:ietf:params:xml:ns:caldav";
+
+const REPORT_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+	<d:prop>
+		<d:getetag/>
+		<c:calendar-data/>
+	</d:prop>
+	<c:filter>
+		<c:comp-filter name="VCALENDAR">
+			<c:comp-filter name="VTODO"/>
+		</c:comp-filter>
+	</c:filter>
+</c:calendar-query>
+"#;
+
+// the parts of an assignment that are kept in sync
+// due dates only go down to the second in iCalendar, so that's all that's compared
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Task {
+	class: String,
+	name: String,
+	due: Option<DateTime<Utc>>,
+	completed: bool,
+}
+
+impl Task {
+	pub fn new(class: String, name: String, due: Option<DateTime<Local>>, completed: bool) -> Self {
+		Self {
+			class,
+			name,
+			due: due.map(|due| Utc.timestamp_opt(due.timestamp(), 0).unwrap()),
+			completed,
+		}
+	}
+
+	// tags aren't synced, so they're whatever the assignment had here
+	fn to_assignment(&self, id: &str, tags: Vec<String>) -> Assignment {
+		Assignment {
+			due_date: self.due.map(|due| due.with_timezone(&Local)),
+			name: self.name.clone(),
+			source: Some(ExternalSource {
+				script: CALDAV_SOURCE.to_string(),
+				id: id.to_string(),
+			}),
+			tags,
+			id: 0,
+		}
+	}
+}
+
+// one VTODO as it was found on the server
+pub struct Todo {
+	pub uid: String,
+	pub summary: String,
+	pub due: Option<DateTime<Local>>,
+	pub completed: bool,
+	pub categories: Vec<String>,
+}
+
+struct RemoteTodo {
+	collection: String,
+	href: String,
+	etag: String,
+	ics: String,
+	categories: Vec<String>,
+	task: Task,
+}
+
+// what every task looked like after the last sync, by iCalendar UID
+// that's what tells which side changed a task since
+#[derive(Serialize, Deserialize, Default)]
+struct Mapping {
+	tasks: BTreeMap<String, Synced>,
+}
+
+// a task as it was last synced, and the collection it was in then
+#[derive(Clone, Serialize, Deserialize)]
+struct Synced {
+	collection: String,
+	task: Task,
+}
+
+impl Mapping {
+	fn path(list_path: &Path) -> PathBuf {
+		let mut path = list_path.as_os_str().to_owned();
+		path.push(".caldav");
+		PathBuf::from(path)
+	}
+
+	fn load(list_path: &Path) -> Self {
+		let path = Self::path(list_path);
+		match fs::read_to_string(&path) {
+			Ok(mapping_str) => serde_json::from_str(&mapping_str).unwrap_or_else(|e| {
+				log::warn!("Ignoring broken CalDAV mapping {}: {}", path.display(), e);
+				Self::default()
+			}),
+			Err(_) => Self::default(),
+		}
+	}
+
+	fn save(&self, list_path: &Path) -> Result<(), String> {
+		let path = Self::path(list_path);
+		fs::write(&path, serde_json::to_string_pretty(self).unwrap())
+			.map_err(|e| format!("Couldn't save CalDAV mapping {}: {}", path.display(), e))
+	}
+}
+
+#[derive(Default)]
+pub struct SyncReport {
+	pub pulled: usize,
+	pub pushed: usize,
+	pub deleted_here: usize,
+	pub deleted_there: usize,
+	pub problems: Vec<String>,
+	// what to compare with next time, kept until the list is saved
+	synced: Mapping,
+}
+
+impl SyncReport {
+	// has to wait until the list with what was pulled is saved, otherwise the next sync
+	// would take the tasks missing from the list as deleted here
+	pub fn save_mapping(&self, list_path: &Path) -> Result<(), String> {
+		self.synced.save(list_path)
+	}
+}
+
+impl fmt::Display for SyncReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Pulled {}, pushed {}, deleted {} here and {} on the server",
+			self.pulled, self.pushed, self.deleted_here, self.deleted_there)?;
+		for problem in &self.problems {
+			write!(f, "\n{}", problem)?;
+		}
+		Ok(())
+	}
+}
+
+enum WriteError {
+	// the ETag didn't match, someone else changed it first
+	Changed,
+	Other(String),
+}
+
+impl fmt::Display for WriteError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			WriteError::Changed => write!(f, "it was changed on the server during the sync"),
+			WriteError::Other(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+fn write_error(e: ureq::Error) -> WriteError {
+	match e {
+		ureq::Error::Status(412, _) => WriteError::Changed,
+		ureq::Error::Status(code, response) => WriteError::Other(format!("{} {}", code, response.status_text())),
+		e => WriteError::Other(e.to_string()),
+	}
+}
+
+struct Client {
+	agent: ureq::Agent,
+	authorization: Option<String>,
+}
+
+impl Client {
+	fn new(caldav: &CaldavConfig) -> Self {
+		let authorization = caldav.username.as_ref().map(|username| {
+			let credentials = format!("{}:{}", username, caldav.password.as_deref().unwrap_or(""));
+			format!("Basic {}", BASE64.encode(credentials))
+		});
+		Self {
+			agent: ureq::AgentBuilder::new()
+				.timeout(Duration::from_secs(30))
+				.build(),
+			authorization,
+		}
+	}
+
+	fn request(&self, method: &str, url: &str) -> ureq::Request {
+		let request = self.agent.request(method, url);
+		match &self.authorization {
+			Some(authorization) => request.set("Authorization", authorization),
+			None => request,
+		}
+	}
+
+	// every VTODO in collection, as (URL, ETag, iCalendar data)
+	fn report(&self, collection: &str) -> Result<Vec<(String, String, String)>, String> {
+		let body = self.request("REPORT", collection)
+			.set("Depth", "1")
+			.set("Content-Type", "application/xml; charset=utf-8")
+			.send_string(REPORT_BODY)
+			.map_err(|e| format!("Couldn't list tasks in {}: {}", collection, write_error(e)))?
+			.into_string()
+			.map_err(|e| format!("Couldn't read tasks in {}: {}", collection, e))?;
+
+		let doc = roxmltree::Document::parse(&body)
+			.map_err(|e| format!("Bad response listing tasks in {}: {}", collection, e))?;
+		let todos = doc.descendants()
+			.filter(|node| node.has_tag_name(("DAV:", "response")))
+			.filter_map(|response| {
+				let text = |namespace: &str, name: &str| {
+					response.descendants()
+						.find(|node| node.has_tag_name((namespace, name)))
+						.and_then(|node| node.text())
+						.map(|text| text.trim().to_string())
+				};
+				Some((resolve_href(collection, &text("DAV:", "href")?), text("DAV:", "getetag")?, text(CALDAV_NS, "calendar-data")?))
+			}).collect();
+		Ok(todos)
+	}
+
+	// etag makes sure nothing changed since it was fetched, without one it has to be new
+	fn put(&self, url: &str, ics: &str, etag: Option<&str>) -> Result<(), WriteError> {
+		let request = self.request("PUT", url)
+			.set("Content-Type", "text/calendar; charset=utf-8");
+		let request = match etag {
+			Some(etag) => request.set("If-Match", etag),
+			None => request.set("If-None-Match", "*"),
+		};
+		request.send_string(ics)
+			.map(|_| ())
+			.map_err(write_error)
+	}
+
+	fn delete(&self, url: &str, etag: &str) -> Result<(), WriteError> {
+		match self.request("DELETE", url).set("If-Match", etag).call() {
+			Ok(_) | Err(ureq::Error::Status(404 | 410, _)) => Ok(()),
+			Err(e) => Err(write_error(e)),
+		}
+	}
+}
+
+// collections are directories, so they need the trailing slash for hrefs to resolve
+fn collection_url(url: &str) -> String {
+	if url.ends_with('/') {
+		url.to_string()
+	}
+	else {
+		format!("{}/", url)
+	}
+}
+
+fn resolve_href(collection: &str, href: &str) -> String {
+	if href.starts_with("http://") || href.starts_with("https://") {
+		href.to_string()
+	}
+	else if href.starts_with('/') {
+		let host_start = collection.find("://").map_or(0, |i| i + 3);
+		let host_end = collection[host_start..].find('/').map_or(collection.len(), |i| host_start + i);
+		format!("{}{}", &collection[..host_end], href)
+	}
+	else {
+		format!("{}{}", collection, href)
+	}
+}
+
+// joins folded lines back together
+pub fn unfold(ics: &str) -> Vec<String> {
+	let mut lines: Vec<String> = vec![];
+	for line in ics.split('\n') {
+		let line = line.strip_suffix('\r').unwrap_or(line);
+		match lines.last_mut() {
+			Some(last) if line.starts_with(' ') || line.starts_with('\t') => last.push_str(&line[1..]),
+			_ if line.is_empty() => (),
+			_ => lines.push(line.to_string()),
+		}
+	}
+	lines
+}
+
+// lines can't be longer than 75 bytes, the rest continues on the next line after a space
+fn fold(line: &str) -> String {
+	let mut folded = String::new();
+	let mut length = 0;
+	for c in line.chars() {
+		if length + c.len_utf8() > 75 {
+			folded.push_str("\r\n ");
+			length = 1;
+		}
+		folded.push(c);
+		length += c.len_utf8();
+	}
+	folded.push_str("\r\n");
+	folded
+}
+
+// "DUE;TZID=Europe/Berlin:20241003T170000" into ("DUE", "TZID=Europe/Berlin", "20241003T170000")
+pub fn split_property(line: &str) -> Option<(String, &str, &str)> {
+	let mut quoted = false;
+	let colon = line.char_indices()
+		.find(|(_, c)| {
+			if *c == '"' {
+				quoted = !quoted;
+			}
+			*c == ':' && !quoted
+		})
+		.map(|(i, _)| i)?;
+	let (name, params) = line[..colon].split_once(';')
+		.unwrap_or((&line[..colon], ""));
+	Some((name.to_ascii_uppercase(), params, &line[colon + 1..]))
+}
+
+fn unescape_text(text: &str) -> String {
+	let mut unescaped = String::new();
+	let mut chars = text.chars();
+	while let Some(c) = chars.next() {
+		if c == '\\' {
+			match chars.next() {
+				Some('n') | Some('N') => unescaped.push('\n'),
+				Some(c) => unescaped.push(c),
+				None => (),
+			}
+		}
+		else {
+			unescaped.push(c);
+		}
+	}
+	unescaped
+}
+
+fn escape_text(text: &str) -> String {
+	text.replace('\\', "\\\\")
+		.replace(';', "\\;")
+		.replace(',', "\\,")
+		.replace('\n', "\\n")
+}
+
+// commas separate values unless they're escaped
+fn split_list(value: &str) -> Vec<String> {
+	let mut values = vec![];
+	let mut current = String::new();
+	let mut escaped = false;
+	for c in value.chars() {
+		if escaped {
+			current.push('\\');
+			current.push(c);
+			escaped = false;
+		}
+		else if c == '\\' {
+			escaped = true;
+		}
+		else if c == ',' {
+			values.push(unescape_text(&current));
+			current.clear();
+		}
+		else {
+			current.push(c);
+		}
+	}
+	values.push(unescape_text(&current));
+	values.into_iter()
+		.filter(|value| !value.is_empty())
+		.collect()
+}
+
+// time zones other than UTC are taken as local time, dates get the default due time
+fn parse_date_time(params: &str, value: &str) -> Option<DateTime<Local>> {
+	if params.to_ascii_uppercase().contains("VALUE=DATE") && !params.to_ascii_uppercase().contains("VALUE=DATE-TIME") || value.len() == 8 {
+		let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+		return Local.from_local_datetime(&date.and_time(config().display.default_due_time())).earliest();
+	}
+
+	match value.strip_suffix('Z') {
+		Some(value) => NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+			.map(|naive| Utc.from_utc_datetime(&naive).with_timezone(&Local)),
+		None => NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+			.and_then(|naive| Local.from_local_datetime(&naive).earliest()),
+	}
+}
+
+fn format_utc(date_time: &DateTime<Utc>) -> String {
+	date_time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+pub fn parse_vtodo(ics: &str) -> Option<Todo> {
+	let mut todo = Todo {
+		uid: String::new(),
+		summary: String::new(),
+		due: None,
+		completed: false,
+		categories: vec![],
+	};
+	let mut found = false;
+	// components nested in the VTODO, like VALARMs, are skipped
+	let mut depth = 0;
+	for line in unfold(ics) {
+		let Some((name, params, value)) = split_property(&line) else {
+			continue;
+		};
+		match name.as_str() {
+			"BEGIN" if value.eq_ignore_ascii_case("VTODO") && depth == 0 => {
+				found = true;
+				depth = 1;
+			},
+			"BEGIN" if depth > 0 => depth += 1,
+			"END" if depth > 0 => {
+				depth -= 1;
+				if depth == 0 {
+					break;
+				}
+			},
+			_ if depth != 1 => (),
+			"UID" => todo.uid = value.to_string(),
+			"SUMMARY" => todo.summary = unescape_text(value),
+			"DUE" => todo.due = parse_date_time(params, value),
+			"STATUS" => todo.completed = value.eq_ignore_ascii_case("COMPLETED"),
+			"COMPLETED" => todo.completed = true,
+			"CATEGORIES" => todo.categories.extend(split_list(value)),
+			_ => (),
+		}
+	}
+
+	if found && !todo.uid.is_empty() {
+		Some(todo)
+	}
+	else {
+		None
+	}
+}
+
+// the properties of task, categories being left alone when there's none given
+fn task_lines(task: &Task, categories: Option<&[String]>) -> Vec<String> {
+	let now = format_utc(&Utc::now());
+	let mut lines = vec![
+		format!("SUMMARY:{}", escape_text(&task.name)),
+		format!("DTSTAMP:{}", now),
+		format!("LAST-MODIFIED:{}", now),
+	];
+	if let Some(due) = &task.due {
+		lines.push(format!("DUE:{}", format_utc(due)));
+	}
+	if task.completed {
+		lines.push("STATUS:COMPLETED".to_string());
+		lines.push(format!("COMPLETED:{}", now));
+		lines.push("PERCENT-COMPLETE:100".to_string());
+	}
+	else {
+		lines.push("STATUS:NEEDS-ACTION".to_string());
+	}
+	if let Some(categories) = categories {
+		if !categories.is_empty() {
+			let categories = categories.iter()
+				.map(|category| escape_text(category))
+				.collect::<Vec<_>>()
+				.join(",");
+			lines.push(format!("CATEGORIES:{}", categories));
+		}
+	}
+	lines
+}
+
+fn new_vtodo(uid: &str, task: &Task, categories: Option<&[String]>) -> String {
+	calendar(vtodo_lines(uid, task, categories))
+}
+
+pub fn vtodo_lines(uid: &str, task: &Task, categories: Option<&[String]>) -> Vec<String> {
+	let mut lines = vec![
+		"BEGIN:VTODO".to_string(),
+		format!("UID:{}", uid),
+	];
+	lines.extend(task_lines(task, categories));
+	lines.push("END:VTODO".to_string());
+	lines
+}
+
+// wraps components up in a VCALENDAR
+pub fn calendar(components: Vec<String>) -> String {
+	let mut lines = vec![
+		"BEGIN:VCALENDAR".to_string(),
+		"VERSION:2.0".to_string(),
+		"PRODID:-//todolist//EN".to_string(),
+	];
+	lines.extend(components);
+	lines.push("END:VCALENDAR".to_string());
+	lines.iter()
+		.map(|line| fold(line))
+		.collect()
+}
+
+// rewrites the properties we sync, keeping everything else the server or other clients put there
+fn update_vtodo(ics: &str, task: &Task, categories: Option<&[String]>) -> String {
+	let replaced = |name: &str| {
+		matches!(name, "SUMMARY" | "DUE" | "DTSTAMP" | "LAST-MODIFIED" | "STATUS" | "COMPLETED" | "PERCENT-COMPLETE")
+			|| (name == "CATEGORIES" && categories.is_some())
+	};
+
+	let mut updated = String::new();
+	let mut depth = 0;
+	for line in unfold(ics) {
+		let (name, value) = match split_property(&line) {
+			Some((name, _params, value)) => (name, value.to_string()),
+			None => (String::new(), String::new()),
+		};
+		match name.as_str() {
+			"BEGIN" if value.eq_ignore_ascii_case("VTODO") && depth == 0 => depth = 1,
+			"BEGIN" if depth > 0 => depth += 1,
+			"END" if depth == 1 => {
+				for line in task_lines(task, categories) {
+					updated.push_str(&fold(&line));
+				}
+				depth = 0;
+			},
+			"END" if depth > 1 => depth -= 1,
+			_ if depth == 1 && replaced(&name) => continue,
+			_ => (),
+		}
+		updated.push_str(&fold(&line));
+	}
+	updated
+}
+
+// the class of a task in the shared collection: the first category that's a class already,
+// otherwise the first category, otherwise the default class
+pub fn class_of_categories(categories: &[String], classes: &[String], caldav: &CaldavConfig) -> String {
+	categories.iter()
+		.find(|category| classes.contains(category))
+		.or(categories.first())
+		.cloned()
+		.unwrap_or_else(|| caldav.default_class().to_string())
+}
+
+// the collection a class goes to, and whether the class goes in the categories there
+fn target(caldav: &CaldavConfig, class: &str) -> Option<(String, bool)> {
+	match (caldav.calendars.get(class), &caldav.url) {
+		(Some(url), _) => Some((collection_url(url), false)),
+		(None, Some(url)) => Some((collection_url(url), true)),
+		(None, None) => None,
+	}
+}
+
+// uploads task, replacing remote if it's already on the server, and returns the collection it's in now
+fn push(client: &Client, caldav: &CaldavConfig, id: &str, task: &Task, remote: Option<&RemoteTodo>, base: Option<&Task>) -> Result<String, WriteError> {
+	let (collection, with_class) = target(caldav, &task.class)
+		.ok_or_else(|| WriteError::Other(format!("there's no calendar for class {}", task.class)))?;
+
+	// the old class is swapped for the new one, other categories stay
+	let categories = with_class.then(|| {
+		let old_classes = [Some(&task.class), base.map(|base| &base.class), remote.map(|remote| &remote.task.class)];
+		let mut categories = vec![task.class.clone()];
+		categories.extend(remote.iter()
+			.flat_map(|remote| remote.categories.iter())
+			.filter(|category| !old_classes.contains(&Some(category)))
+			.cloned());
+		categories
+	});
+	let categories = categories.as_deref();
+
+	match remote {
+		Some(remote) if remote.collection == collection => {
+			client.put(&remote.href, &update_vtodo(&remote.ics, task, categories), Some(&remote.etag))?
+		},
+		// moved to a class with another calendar
+		Some(remote) => {
+			client.put(&format!("{}{}.ics", collection, id), &update_vtodo(&remote.ics, task, categories), None)?;
+			client.delete(&remote.href, &remote.etag)?
+		},
+		None => client.put(&format!("{}{}.ics", collection, id), &new_vtodo(id, task, categories), None)?,
+	}
+	Ok(collection)
+}
+
+// makes the local list match task, creating the assignment if there's no uid
+fn pull(todolist: &mut TodoList, id: &str, uid: Option<u64>, task: &Task) -> Result<(), ()> {
+	let tags = uid.and_then(|uid| todolist.get_assignment(uid).ok())
+		.map(|assign| assign.tags)
+		.unwrap_or_default();
+	match uid {
+		Some(uid) if todolist.get_assignment_class(uid).as_ref() == Some(&task.class) => {
+			let uid = todolist.edit_assignment(uid, task.to_assignment(id, tags))?;
+			todolist.set_assignment_completion(uid, task.completed)
+		},
+		// moving between classes is a delete and a create
+		_ => {
+			if let Some(uid) = uid {
+				todolist.delete_assignment(uid)?;
+			}
+			// it's fine if the class is there already
+			let _ = todolist.create_class(task.class.clone());
+			let uid = todolist.create_assignment(task.class.clone(), task.to_assignment(id, tags))?;
+			todolist.set_assignment_completion(uid, task.completed)
+		},
+	}
+}
+
+// two-way sync of the list with the configured collections
+// changes on only one side since the last sync win, changes on both go to caldav.prefer,
+// and writes to the server only go through if its ETag still matches what was fetched
+pub fn sync(todolist: &mut TodoList, caldav: &CaldavConfig) -> Result<SyncReport, String> {
+	if !caldav.is_configured() {
+		return Err("No CalDAV server set in the config file".to_string());
+	}
+
+	let client = Client::new(caldav);
+	let mut report = SyncReport::default();
+	let classes = todolist.get_classes();
+
+	// every collection, and the class it's for if it's a class' own calendar
+	let collections = caldav.url.iter()
+		.map(|url| (collection_url(url), None))
+		.chain(caldav.calendars.iter()
+			.map(|(class, url)| (collection_url(url), Some(class.clone()))));
+	let mut remote: BTreeMap<String, RemoteTodo> = BTreeMap::new();
+	// collections where every task could be read, so one missing from them was deleted there
+	let mut complete: BTreeSet<String> = BTreeSet::new();
+	for (collection, class) in collections {
+		let mut unreadable = 0;
+		for (href, etag, ics) in client.report(&collection)? {
+			let Some(todo) = parse_vtodo(&ics) else {
+				log::warn!("Skipping {}, there's no VTODO in it", href);
+				unreadable += 1;
+				continue;
+			};
+			let class = class.clone()
+				.unwrap_or_else(|| class_of_categories(&todo.categories, &classes, caldav));
+			remote.insert(todo.uid, RemoteTodo {
+				collection: collection.clone(),
+				href,
+				etag,
+				ics,
+				categories: todo.categories,
+				task: Task::new(class, todo.summary, todo.due, todo.completed),
+			});
+		}
+		if unreadable == 0 {
+			complete.insert(collection);
+		}
+		else {
+			report.problems.push(format!("Couldn't read {} task(s) in {}, so none of its tasks are deleted here", unreadable, collection));
+		}
+	}
+
+	// assignments from the server, and ones that were never synced
+	// the ones from scripts are left to their scripts
+	let mut local: BTreeMap<String, (u64, Task)> = BTreeMap::new();
+	let mut unsynced: Vec<(u64, Task)> = vec![];
+	for (class, assignments) in todolist.snapshot() {
+		for (assign, completed) in assignments.into_values() {
+			let uid = TodoList::hash_assignment(&assign);
+			let task = Task::new(class.clone(), assign.name.clone(), assign.due_date, completed);
+			match assign.source {
+				Some(source) if source.script == CALDAV_SOURCE => {
+					local.insert(source.id, (uid, task));
+				},
+				Some(_) => (),
+				None => unsynced.push((uid, task)),
+			}
+		}
+	}
+
+	let mapping = Mapping::load(todolist.get_list_path());
+	let ids = mapping.tasks.keys()
+		.chain(local.keys())
+		.chain(remote.keys())
+		.cloned()
+		.collect::<BTreeSet<String>>();
+
+	let mut synced = Mapping::default();
+	for id in ids {
+		let base = mapping.tasks.get(&id);
+		let here = local.get(&id);
+		let there = remote.get(&id);
+
+		let push_here = |task: &Task, remote: Option<&RemoteTodo>, report: &mut SyncReport| {
+			match push(&client, caldav, &id, task, remote, base.map(|base| &base.task)) {
+				Ok(collection) => {
+					report.pushed += 1;
+					Some(Synced {
+						collection,
+						task: task.clone(),
+					})
+				},
+				Err(e) => {
+					report.problems.push(format!("Couldn't upload \"{}\": {}", task.name, e));
+					base.cloned()
+				},
+			}
+		};
+		let pull_there = |todolist: &mut TodoList, uid: Option<u64>, theirs: &RemoteTodo, report: &mut SyncReport| {
+			match pull(todolist, &id, uid, &theirs.task) {
+				Ok(()) => {
+					report.pulled += 1;
+					Some(Synced {
+						collection: theirs.collection.clone(),
+						task: theirs.task.clone(),
+					})
+				},
+				Err(()) => {
+					report.problems.push(format!("Couldn't update \"{}\" from the server", theirs.task.name));
+					base.cloned()
+				},
+			}
+		};
+
+		let task = match (base, here, there) {
+			(_, Some((_uid, ours)), Some(theirs)) if *ours == theirs.task => Some(Synced {
+				collection: theirs.collection.clone(),
+				task: ours.clone(),
+			}),
+			(_, Some((uid, ours)), Some(theirs)) => {
+				let base = base.map(|base| &base.task);
+				let ours_changed = base != Some(ours);
+				let theirs_changed = base != Some(&theirs.task);
+				let take_theirs = if ours_changed && theirs_changed {
+					report.problems.push(format!("\"{}\" was changed here and on the server, kept the {} version",
+						ours.name, if caldav.prefer == CaldavPrefer::Remote { "server's" } else { "local" }));
+					caldav.prefer == CaldavPrefer::Remote
+				}
+				else {
+					theirs_changed
+				};
+
+				if take_theirs {
+					pull_there(todolist, Some(*uid), theirs, &mut report)
+				}
+				else {
+					push_here(ours, Some(theirs), &mut report)
+				}
+			},
+			// gone from the server, unless it was changed here since
+			(Some(base), Some((uid, ours)), None) if base.task == *ours && complete.contains(&base.collection) => {
+				if todolist.delete_assignment(*uid).is_ok() {
+					report.deleted_here += 1;
+				}
+				None
+			},
+			// its collection wasn't read, so it may well still be there
+			// it's kept as an assignment of our own, which gets uploaded again where its class goes now
+			(Some(base), Some((uid, ours)), None) if base.task == *ours => {
+				if todolist.set_assignment_source(*uid, None).is_ok() {
+					report.problems.push(format!("\"{}\" wasn't found in a calendar that was read, kept it here without syncing it", ours.name));
+				}
+				None
+			},
+			(_, Some((_uid, ours)), None) => push_here(ours, None, &mut report),
+			// deleted here, unless it was changed on the server since
+			(Some(base), None, Some(theirs)) if base.task == theirs.task => {
+				match client.delete(&theirs.href, &theirs.etag) {
+					Ok(()) => {
+						report.deleted_there += 1;
+						None
+					},
+					Err(e) => {
+						report.problems.push(format!("Couldn't delete \"{}\" from the server: {}", base.task.name, e));
+						Some(base.clone())
+					},
+				}
+			},
+			(_, None, Some(theirs)) => pull_there(todolist, None, theirs, &mut report),
+			(_, None, None) => None,
+		};
+
+		if let Some(task) = task {
+			synced.tasks.insert(id, task);
+		}
+	}
+
+	for (uid, task) in unsynced {
+		if target(caldav, &task.class).is_none() {
+			continue;
+		}
+		let id = format!("{:016x}-{}@todolist", uid, Utc::now().timestamp());
+		match push(&client, caldav, &id, &task, None, None) {
+			Ok(collection) => {
+				report.pushed += 1;
+				let source = ExternalSource {
+					script: CALDAV_SOURCE.to_string(),
+					id: id.clone(),
+				};
+				if todolist.set_assignment_source(uid, Some(source)).is_ok() {
+					synced.tasks.insert(id, Synced {
+						collection,
+						task,
+					});
+				}
+			},
+			Err(e) => report.problems.push(format!("Couldn't upload \"{}\": {}", task.name, e)),
+		}
+	}
+
+	// what came from the server isn't something to undo here
+	if report.pulled > 0 || report.deleted_here > 0 {
+		todolist.clear_history();
+	}
+
+	report.synced = synced;
+	log::info!("CalDAV sync done: {}", report.to_string().replace('\n', "; "));
+	Ok(report)
+}
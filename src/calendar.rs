@@ -0,0 +1,223 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use chrono::{prelude::*, Days, Months, NaiveDate};
+use cursive::{Cursive, Printer, Vec2, View};
+use cursive::direction::Direction;
+use cursive::event::{Callback, Event, EventResult, Key};
+use cursive::theme::{ColorStyle, Effect};
+use cursive::view::CannotFocus;
+
+// columns per day (and a space between days), and two lines for each week
+const GRID: MonthGrid = MonthGrid {
+	cell_width: 6,
+	cell_height: 2,
+};
+
+type DateCallback = Arc<dyn Fn(&mut Cursive, NaiveDate) + Send + Sync>;
+
+// a month laid out as a grid, with how many assignments are due each day and which classes they're for
+// the view doesn't know about the list, so whoever owns it fills it in with set_due when the month changes
+pub struct MonthView {
+	// always the first of the month
+	month: NaiveDate,
+	selected: NaiveDate,
+	// the class of every assignment due on each day
+	due: HashMap<NaiveDate, Vec<String>>,
+	on_submit: Option<DateCallback>,
+	on_month_change: Option<DateCallback>,
+}
+
+impl MonthView {
+	pub fn new(selected: NaiveDate) -> Self {
+		Self {
+			month: selected.with_day(1).unwrap(),
+			selected,
+			due: HashMap::new(),
+			on_submit: None,
+			on_month_change: None,
+		}
+	}
+
+	// called with the selected day on Enter
+	pub fn on_submit<F>(mut self, f: F) -> Self
+		where F: Fn(&mut Cursive, NaiveDate) + Send + Sync + 'static
+	{
+		self.on_submit = Some(Arc::new(f));
+		self
+	}
+
+	// called with the first of the month whenever a different month is shown
+	pub fn on_month_change<F>(mut self, f: F) -> Self
+		where F: Fn(&mut Cursive, NaiveDate) + Send + Sync + 'static
+	{
+		self.on_month_change = Some(Arc::new(f));
+		self
+	}
+
+	// the first and last day shown
+	pub fn range(&self) -> (NaiveDate, NaiveDate) {
+		let last = self.month.checked_add_months(Months::new(1)).unwrap()
+			.pred_opt().unwrap();
+		(self.month, last)
+	}
+
+	pub fn set_due(&mut self, due: HashMap<NaiveDate, Vec<String>>) {
+		self.due = due;
+	}
+
+	fn select(&mut self, date: NaiveDate) -> EventResult {
+		self.selected = date;
+		let month = date.with_day(1).unwrap();
+		if month == self.month {
+			return EventResult::Consumed(None);
+		}
+
+		self.month = month;
+		self.due.clear();
+		match self.on_month_change.clone() {
+			Some(f) => EventResult::with_cb(move |s| f(s, month)),
+			None => EventResult::Consumed(None),
+		}
+	}
+}
+
+impl View for MonthView {
+	fn draw(&self, printer: &Printer) {
+		let title = format!("< {} >", self.month.format("%B %Y"));
+		let style = if printer.focused {
+			ColorStyle::highlight()
+		}
+		else {
+			ColorStyle::highlight_inactive()
+		};
+		GRID.draw(printer, self.month, &title, self.selected, style, |printer, date, (x, y)| {
+			let classes = self.due.get(&date);
+			let count = match classes {
+				Some(classes) => format!("{:>3}", classes.len()),
+				None => String::new(),
+			};
+			// the first letter of each class, as many as fit
+			let markers = classes.map(|classes| {
+				classes.iter()
+					.collect::<BTreeSet<_>>()
+					.iter()
+					.filter_map(|class| class.chars().next())
+					.take(GRID.cell_width)
+					.collect::<String>()
+			}).unwrap_or_default();
+
+			printer.print((x, y), &format!("{:>2} {:<3}", date.day(), count));
+			printer.print((x, y + 1), &format!("{:<1$}", markers, GRID.cell_width));
+		});
+	}
+
+	fn required_size(&mut self, _constraint: Vec2) -> Vec2 {
+		GRID.size()
+	}
+
+	fn on_event(&mut self, event: Event) -> EventResult {
+		if event == Event::Key(Key::Enter) {
+			let selected = self.selected;
+			return match self.on_submit.clone() {
+				Some(f) => EventResult::Consumed(Some(Callback::from_fn(move |s| f(s, selected)))),
+				None => EventResult::Consumed(None),
+			};
+		}
+
+		match move_selection(self.selected, &event) {
+			Some(Some(date)) => self.select(date),
+			// out of chrono's range
+			Some(None) => EventResult::Consumed(None),
+			None => EventResult::Ignored,
+		}
+	}
+
+	fn take_focus(&mut self, _source: Direction) -> Result<EventResult, CannotFocus> {
+		Ok(EventResult::Consumed(None))
+	}
+}
+
+// a month as a grid of days under its title and the weekday names, weeks starting on Monday
+// each day gets cell_width columns with one between days, and cell_height lines
+pub struct MonthGrid {
+	pub cell_width: usize,
+	pub cell_height: usize,
+}
+
+impl MonthGrid {
+	pub fn width(&self) -> usize {
+		7 * (self.cell_width + 1) - 1
+	}
+
+	// always room for six weeks, so flipping through months doesn't move anything around
+	pub fn size(&self) -> Vec2 {
+		Vec2::new(self.width(), 2 + 6 * self.cell_height)
+	}
+
+	// draw_day fills in a day's cell from the position given, styled for whether it's selected
+	// and in bold for today
+	pub fn draw<F>(&self, printer: &Printer, month: NaiveDate, title: &str, selected: NaiveDate, selected_style: ColorStyle, draw_day: F)
+		where F: Fn(&Printer, NaiveDate, (usize, usize))
+	{
+		printer.print((self.width().saturating_sub(title.len()) / 2, 0), title);
+		for (i, weekday) in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"].iter().enumerate() {
+			printer.print((i * (self.cell_width + 1), 1), weekday);
+		}
+
+		let today = Local::now().date_naive();
+		let first = month.weekday().num_days_from_monday() as usize;
+		for date in month.iter_days().take_while(|date| date.month() == month.month()) {
+			let cell = first + date.day0() as usize;
+			let pos = ((cell % 7) * (self.cell_width + 1), 2 + (cell / 7) * self.cell_height);
+			let style = if date == selected {
+				selected_style
+			}
+			else {
+				ColorStyle::primary()
+			};
+			printer.with_color(style, |printer| {
+				if date == today {
+					printer.with_effect(Effect::Bold, |printer| draw_day(printer, date, pos));
+				}
+				else {
+					draw_day(printer, date, pos);
+				}
+			});
+		}
+	}
+}
+
+// where a key moves the selected day: arrows by a day or a week, PgUp/PgDn or < > by a month,
+// Home or t back to today
+// None if it's not one of those keys, Some(None) if the day would be out of range
+pub fn move_selection(selected: NaiveDate, event: &Event) -> Option<Option<NaiveDate>> {
+	let date = match event {
+		Event::Key(Key::Left) => selected.pred_opt(),
+		Event::Key(Key::Right) => selected.succ_opt(),
+		Event::Key(Key::Up) => selected.checked_sub_days(Days::new(7)),
+		Event::Key(Key::Down) => selected.checked_add_days(Days::new(7)),
+		Event::Key(Key::PageUp) | Event::Char('<') => selected.checked_sub_months(Months::new(1)),
+		Event::Key(Key::PageDown) | Event::Char('>') => selected.checked_add_months(Months::new(1)),
+		Event::Key(Key::Home) | Event::Char('t') => Some(Local::now().date_naive()),
+		_ => return None,
+	};
+	Some(date)
+}
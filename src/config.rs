@@ -0,0 +1,488 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::NaiveTime;
+use chrono::format::{Item, StrftimeItems};
+use serde::Deserialize;
+use toml_edit::DocumentMut;
+
+use crate::filter::Filter;
+use crate::keys::{Action, Keystroke};
+use directories::BaseDirs;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// everything read from config.toml in the config directory
+// missing keys fall back to their defaults, unknown keys are an error
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub paths: PathsConfig,
+	pub week: WeekConfig,
+	pub display: DisplayConfig,
+	pub history: HistoryConfig,
+	pub autosave: AutosaveConfig,
+	pub sandbox: SandboxConfig,
+	pub git: GitConfig,
+	pub caldav: CaldavConfig,
+	pub server: ServerConfig,
+	pub keys: KeysConfig,
+	// saved filters by name, shown next to the classes
+	pub smart_lists: BTreeMap<String, String>,
+	// where this came from, for saving settings changed in the UI
+	#[serde(skip)]
+	path: PathBuf,
+}
+
+// overrides for where things are kept, the defaults come from ProjectDirs
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+	// directory holding the list file
+	pub data: Option<PathBuf>,
+	// directory holding the provider scripts
+	pub scripts: Option<PathBuf>,
+	// the log file itself
+	pub log: Option<PathBuf>,
+}
+
+// which days the week view shows, relative to today
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeekConfig {
+	pub days_before: u64,
+	pub days_after: u64,
+}
+
+// about ten years either way, further than that the date arithmetic runs out
+const MAX_WEEK_DAYS: u64 = 3650;
+
+impl Default for WeekConfig {
+	fn default() -> Self {
+		Self {
+			days_before: 3,
+			days_after: 10,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+	// columns given to the class name in the week view
+	pub class_width: usize,
+	// columns given to the assignment name in the week view
+	pub name_width: usize,
+	// strftime format for due times in the week view
+	pub time_format: String,
+	// HH:MM pre-filled in the new assignment dialog
+	pub default_due_time: String,
+	// how the week view shows completed assignments, changed from the UI too
+	pub completed: CompletedDisplay,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletedDisplay {
+	#[default]
+	Show,
+	// struck through and greyed out
+	Dim,
+	Hide,
+	// hidden behind a count for each day, which can be expanded
+	Collapse,
+}
+
+impl CompletedDisplay {
+	pub fn name(&self) -> &'static str {
+		match self {
+			CompletedDisplay::Show => "show",
+			CompletedDisplay::Dim => "dim",
+			CompletedDisplay::Hide => "hide",
+			CompletedDisplay::Collapse => "collapse",
+		}
+	}
+
+	pub fn next(&self) -> Self {
+		match self {
+			CompletedDisplay::Show => CompletedDisplay::Dim,
+			CompletedDisplay::Dim => CompletedDisplay::Collapse,
+			CompletedDisplay::Collapse => CompletedDisplay::Hide,
+			CompletedDisplay::Hide => CompletedDisplay::Show,
+		}
+	}
+}
+
+impl Default for DisplayConfig {
+	fn default() -> Self {
+		Self {
+			class_width: 8,
+			name_width: 32,
+			time_format: "%l:%M %p".to_string(),
+			default_due_time: "08:00".to_string(),
+			completed: CompletedDisplay::Show,
+		}
+	}
+}
+
+impl DisplayConfig {
+	pub fn default_due_time(&self) -> NaiveTime {
+		// checked in Config::validate
+		NaiveTime::parse_from_str(&self.default_due_time, "%H:%M").unwrap()
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+	// how many changes can be undone
+	pub limit: usize,
+	// whether the undo history is saved next to the list
+	pub persist: bool,
+}
+
+impl Default for HistoryConfig {
+	fn default() -> Self {
+		Self {
+			limit: 100,
+			persist: false,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutosaveConfig {
+	pub enabled: bool,
+	// seconds without changes before saving
+	pub delay_secs: u64,
+}
+
+impl Default for AutosaveConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			delay_secs: 2,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+	// can also be turned off for one run with --no-sandbox
+	pub enabled: bool,
+	// extra paths readable by the application and its scripts
+	pub read: Vec<PathBuf>,
+	// extra paths readable and writable by the application
+	pub write: Vec<PathBuf>,
+	// whether TCP connections are allowed at all
+	pub network: bool,
+	// whether to filter syscalls with seccomp as well
+	pub seccomp: bool,
+}
+
+impl Default for SandboxConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			read: Vec::new(),
+			write: Vec::new(),
+			network: false,
+			seccomp: false,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+	// whether the data directory is a git repository with a commit for every save
+	pub enabled: bool,
+	// what `todolist sync` pulls from and pushes to, a URL or a path
+	pub remote: Option<String>,
+	pub branch: String,
+	// who the commits are made by
+	pub name: String,
+	pub email: String,
+}
+
+impl Default for GitConfig {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			remote: None,
+			branch: "main".to_string(),
+			name: "todolist".to_string(),
+			email: "todolist@localhost".to_string(),
+		}
+	}
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaldavConfig {
+	// task collection for every class without a calendar of its own, with the class as a category
+	pub url: Option<String>,
+	// classes with a task collection of their own
+	pub calendars: BTreeMap<String, String>,
+	pub username: Option<String>,
+	pub password: Option<String>,
+	// class for tasks in url that have no category
+	pub default_class: Option<String>,
+	// which side wins when a task was changed both here and on the server
+	pub prefer: CaldavPrefer,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaldavPrefer {
+	#[default]
+	Remote,
+	Local,
+}
+
+impl CaldavConfig {
+	pub fn is_configured(&self) -> bool {
+		self.url.is_some() || !self.calendars.is_empty()
+	}
+
+	pub fn default_class(&self) -> &str {
+		self.default_class.as_deref().unwrap_or("Tasks")
+	}
+}
+
+// for `todolist serve`
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+	// address and port to listen on
+	pub bind: String,
+	// what clients send as "Authorization: Bearer TOKEN", a new one is made up on every start if unset
+	pub token: Option<String>,
+}
+
+impl Default for ServerConfig {
+	fn default() -> Self {
+		Self {
+			bind: "127.0.0.1:7878".to_string(),
+			token: None,
+		}
+	}
+}
+
+// what each action's key is, see Keystroke for how they're written
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+	pub add: String,
+	pub edit: String,
+	pub delete: String,
+	pub toggle: String,
+	pub save: String,
+	pub quit: String,
+	pub help: String,
+	pub undo: String,
+	pub redo: String,
+	pub search: String,
+	pub palette: String,
+	pub menu: String,
+}
+
+impl Default for KeysConfig {
+	fn default() -> Self {
+		Self {
+			add: "a".to_string(),
+			edit: "e".to_string(),
+			delete: "d".to_string(),
+			toggle: "space".to_string(),
+			save: "s".to_string(),
+			quit: "q".to_string(),
+			help: "?".to_string(),
+			undo: "ctrl-z".to_string(),
+			redo: "ctrl-y".to_string(),
+			search: "/".to_string(),
+			palette: "ctrl-p".to_string(),
+			menu: "f10".to_string(),
+		}
+	}
+}
+
+impl KeysConfig {
+	pub fn key(&self, action: Action) -> Keystroke {
+		// checked in Config::validate
+		Keystroke::parse(self.key_str(action)).unwrap()
+	}
+
+	fn key_str(&self, action: Action) -> &str {
+		match action {
+			Action::Add => &self.add,
+			Action::Edit => &self.edit,
+			Action::Delete => &self.delete,
+			Action::Toggle => &self.toggle,
+			Action::Save => &self.save,
+			Action::Quit => &self.quit,
+			Action::Help => &self.help,
+			Action::Undo => &self.undo,
+			Action::Redo => &self.redo,
+			Action::Search => &self.search,
+			Action::Palette => &self.palette,
+			Action::Menu => &self.menu,
+		}
+	}
+}
+
+impl Config {
+	// a missing config file is fine, a broken one isn't
+	pub fn load(path: &Path) -> Result<Self, String> {
+		if !path.exists() {
+			return Ok(Self {
+				path: path.to_path_buf(),
+				..Self::default()
+			});
+		}
+
+		let config_str = fs::read_to_string(path)
+			.map_err(|e| format!("Couldn't read config file {}: {}", path.display(), e))?;
+		let mut config: Self = toml::from_str(&config_str)
+			.map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+
+		config.validate()
+			.map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+
+		config.paths.data = config.paths.data.map(|path| expand_home(&path));
+		config.paths.scripts = config.paths.scripts.map(|path| expand_home(&path));
+		config.paths.log = config.paths.log.map(|path| expand_home(&path));
+		config.sandbox.read = config.sandbox.read.iter()
+			.map(|path| expand_home(path))
+			.collect();
+		config.sandbox.write = config.sandbox.write.iter()
+			.map(|path| expand_home(path))
+			.collect();
+		config.path = path.to_path_buf();
+
+		Ok(config)
+	}
+
+	// writes one setting back to the config file, leaving the rest of it (comments and all) alone
+	// None takes the setting out instead
+	// the loaded config doesn't change, whoever changed the setting has to keep track of it
+	pub fn save_setting(&self, table: &str, key: &str, value: Option<&str>) -> Result<(), String> {
+		let config_str = match fs::read_to_string(&self.path) {
+			Ok(config_str) => config_str,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(format!("Couldn't read config file {}: {}", self.path.display(), e)),
+		};
+		let mut doc = config_str.parse::<DocumentMut>()
+			.map_err(|e| format!("Invalid config file {}: {}", self.path.display(), e))?;
+		if !doc.contains_key(table) {
+			doc.insert(table, toml_edit::table());
+		}
+		match value {
+			Some(value) => doc[table][key] = toml_edit::value(value),
+			None => {
+				if let Some(table) = doc[table].as_table_like_mut() {
+					table.remove(key);
+				}
+			},
+		}
+		fs::write(&self.path, doc.to_string())
+			.map_err(|e| format!("Couldn't write config file {}: {}", self.path.display(), e))
+	}
+
+	fn validate(&self) -> Result<(), String> {
+		if self.week.days_before > MAX_WEEK_DAYS {
+			return Err(format!("week.days_before can be at most {}", MAX_WEEK_DAYS));
+		}
+		if self.week.days_after > MAX_WEEK_DAYS {
+			return Err(format!("week.days_after can be at most {}", MAX_WEEK_DAYS));
+		}
+		if self.display.class_width == 0 {
+			return Err("display.class_width must be at least 1".to_string());
+		}
+		if self.display.name_width == 0 {
+			return Err("display.name_width must be at least 1".to_string());
+		}
+		let bad_format = StrftimeItems::new(&self.display.time_format)
+			.any(|item| item == Item::Error);
+		if self.display.time_format.is_empty() || bad_format {
+			return Err(format!("display.time_format \"{}\" isn't a valid strftime format", self.display.time_format));
+		}
+		if NaiveTime::parse_from_str(&self.display.default_due_time, "%H:%M").is_err() {
+			return Err(format!("display.default_due_time \"{}\" isn't a HH:MM time", self.display.default_due_time));
+		}
+
+		let urls = self.caldav.url.iter()
+			.chain(self.caldav.calendars.values());
+		for url in urls {
+			if !url.starts_with("http://") && !url.starts_with("https://") {
+				return Err(format!("caldav URL \"{}\" isn't an http(s) URL", url));
+			}
+		}
+		if self.caldav.password.is_some() && self.caldav.username.is_none() {
+			return Err("caldav.password is set without caldav.username".to_string());
+		}
+		if self.server.bind.parse::<SocketAddr>().is_err() {
+			return Err(format!("server.bind \"{}\" isn't an address and port", self.server.bind));
+		}
+		if self.server.token.as_ref().is_some_and(|token| token.is_empty()) {
+			return Err("server.token can't be empty".to_string());
+		}
+		if self.git.branch.is_empty() || self.git.branch.starts_with('-') {
+			return Err(format!("git.branch \"{}\" isn't a valid branch name", self.git.branch));
+		}
+		let mut bound: Vec<(Keystroke, Action)> = vec![];
+		for action in Action::ALL {
+			let key = Keystroke::parse(self.keys.key_str(action))
+				.map_err(|e| format!("keys.{}: {}", action.name(), e))?;
+			if let Some((_, other)) = bound.iter().find(|(other_key, _)| *other_key == key) {
+				return Err(format!("keys.{} and keys.{} are both {}", other.name(), action.name(), key));
+			}
+			bound.push((key, action));
+		}
+		for (name, query) in &self.smart_lists {
+			Filter::parse(query)
+				.map_err(|e| format!("smart_lists.{}: {}", name, e))?;
+		}
+
+		Ok(())
+	}
+}
+
+// makes the config available to the rest of the application, can only be done once
+pub fn set_config(config: Config) {
+	let _ = CONFIG.set(config);
+}
+
+pub fn config() -> &'static Config {
+	CONFIG.get_or_init(Config::default)
+}
+
+// turns a leading "~" into the user's home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+	match (path.strip_prefix("~"), BaseDirs::new()) {
+		(Ok(rest), Some(base_dirs)) => base_dirs.home_dir().join(rest),
+		_ => path.to_path_buf(),
+	}
+}
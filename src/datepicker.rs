@@ -0,0 +1,186 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+use chrono::{prelude::*, NaiveDate, NaiveDateTime, NaiveTime};
+use cursive::{Cursive, Printer, Vec2, View};
+use cursive::direction::Direction;
+use cursive::event::{Callback, Event, EventResult, Key};
+use cursive::theme::ColorStyle;
+use cursive::view::CannotFocus;
+
+use crate::calendar::{MonthGrid, move_selection};
+
+// two columns and a line for each day
+const GRID: MonthGrid = MonthGrid {
+	cell_width: 2,
+	cell_height: 1,
+};
+
+type ValueCallback = Arc<dyn Fn(&mut Cursive, NaiveDateTime) + Send + Sync>;
+
+// which part of the picker the keys go to, Tab moves on to the next
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+	Date,
+	Hour,
+	Minute,
+}
+
+// a small month calendar with a time under it, for picking when something is due
+// arrow keys move between days and PgUp/PgDn between months, then Tab goes to the hour and the minute,
+// which Up and Down turn like a spinner
+pub struct DatePicker {
+	date: NaiveDate,
+	time: NaiveTime,
+	field: Field,
+	on_submit: Option<ValueCallback>,
+}
+
+impl DatePicker {
+	pub fn new(value: NaiveDateTime) -> Self {
+		let mut picker = Self {
+			date: value.date(),
+			time: NaiveTime::MIN,
+			field: Field::Date,
+			on_submit: None,
+		};
+		picker.set_value(value);
+		picker
+	}
+
+	// called with what's picked on Enter
+	pub fn on_submit<F>(mut self, f: F) -> Self
+		where F: Fn(&mut Cursive, NaiveDateTime) + Send + Sync + 'static
+	{
+		self.on_submit = Some(Arc::new(f));
+		self
+	}
+
+	pub fn value(&self) -> NaiveDateTime {
+		self.date.and_time(self.time)
+	}
+
+	pub fn set_value(&mut self, value: NaiveDateTime) {
+		self.date = value.date();
+		// the picker only goes down to the minute
+		self.time = NaiveTime::from_hms_opt(value.hour(), value.minute(), 0).unwrap();
+	}
+
+	// hours go one at a time and minutes five at a time, wrapping around without changing the day
+	fn spin(&mut self, up: bool) {
+		let (hour, minute) = (self.time.hour(), self.time.minute());
+		let (hour, minute) = match (self.field, up) {
+			(Field::Hour, true) => ((hour + 1) % 24, minute),
+			(Field::Hour, false) => ((hour + 23) % 24, minute),
+			(Field::Minute, true) => (hour, (minute / 5 + 1) * 5 % 60),
+			(Field::Minute, false) if minute % 5 != 0 => (hour, minute - minute % 5),
+			(Field::Minute, false) => (hour, (minute + 55) % 60),
+			(Field::Date, _) => return,
+		};
+		self.time = NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+	}
+
+	// how the selected day and the time are highlighted depends on where the keys go
+	fn style(&self, field: Field, focused: bool) -> ColorStyle {
+		if focused && self.field == field {
+			ColorStyle::highlight()
+		}
+		else if field == Field::Date {
+			ColorStyle::highlight_inactive()
+		}
+		else {
+			ColorStyle::primary()
+		}
+	}
+}
+
+impl View for DatePicker {
+	fn draw(&self, printer: &Printer) {
+		let month = self.date.with_day(1).unwrap();
+		let title = month.format("%B %Y").to_string();
+		let style = self.style(Field::Date, printer.focused);
+		GRID.draw(printer, month, &title, self.date, style, |printer, date, pos| {
+			printer.print(pos, &format!("{:>2}", date.day()));
+		});
+
+		// the time goes under the right edge of the calendar, after a blank line
+		let y = GRID.size().y + 1;
+		let x = GRID.width() - 5;
+		printer.print((0, y), "Time");
+		printer.with_color(self.style(Field::Hour, printer.focused), |printer| {
+			printer.print((x, y), &format!("{:02}", self.time.hour()));
+		});
+		printer.print((x + 2, y), ":");
+		printer.with_color(self.style(Field::Minute, printer.focused), |printer| {
+			printer.print((x + 3, y), &format!("{:02}", self.time.minute()));
+		});
+	}
+
+	fn required_size(&mut self, _constraint: Vec2) -> Vec2 {
+		GRID.size() + (0, 2)
+	}
+
+	fn on_event(&mut self, event: Event) -> EventResult {
+		if event == Event::Key(Key::Enter) {
+			let value = self.value();
+			return match self.on_submit.clone() {
+				Some(f) => EventResult::Consumed(Some(Callback::from_fn(move |s| f(s, value)))),
+				None => EventResult::Consumed(None),
+			};
+		}
+
+		// Tab past the minute (or Shift-Tab past the date) leaves the picker
+		let field = match (self.field, &event) {
+			(Field::Date, Event::Key(Key::Tab)) => Field::Hour,
+			(Field::Hour, Event::Key(Key::Tab)) => Field::Minute,
+			(Field::Minute, Event::Shift(Key::Tab)) => Field::Hour,
+			(Field::Hour, Event::Shift(Key::Tab)) => Field::Date,
+			(_, Event::Key(Key::Tab) | Event::Shift(Key::Tab)) => return EventResult::Ignored,
+			(Field::Hour, Event::Key(Key::Left)) => Field::Date,
+			(Field::Hour, Event::Key(Key::Right)) => Field::Minute,
+			(Field::Minute, Event::Key(Key::Left)) => Field::Hour,
+			(Field::Hour | Field::Minute, Event::Key(Key::Up)) => {
+				self.spin(true);
+				return EventResult::Consumed(None);
+			},
+			(Field::Hour | Field::Minute, Event::Key(Key::Down)) => {
+				self.spin(false);
+				return EventResult::Consumed(None);
+			},
+			(Field::Date, _) => {
+				return match move_selection(self.date, &event) {
+					Some(Some(date)) => {
+						self.date = date;
+						EventResult::Consumed(None)
+					},
+					// out of chrono's range
+					Some(None) => EventResult::Consumed(None),
+					None => EventResult::Ignored,
+				};
+			},
+			_ => return EventResult::Ignored,
+		};
+		self.field = field;
+		EventResult::Consumed(None)
+	}
+
+	fn take_focus(&mut self, _source: Direction) -> Result<EventResult, CannotFocus> {
+		Ok(EventResult::Consumed(None))
+	}
+}
@@ -0,0 +1,323 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use chrono::{prelude::*, Days, Months, NaiveDate, NaiveTime};
+
+// what a word or two of the due field turned out to be
+enum Part {
+	Date(NaiveDate),
+	Time(NaiveTime),
+	// "at", "on" and the like
+	Filler,
+}
+
+const WEEKDAYS: [(&[&str], Weekday); 7] = [
+	(&["mon", "monday"], Weekday::Mon),
+	(&["tue", "tues", "tuesday"], Weekday::Tue),
+	(&["wed", "weds", "wednesday"], Weekday::Wed),
+	(&["thu", "thur", "thurs", "thursday"], Weekday::Thu),
+	(&["fri", "friday"], Weekday::Fri),
+	(&["sat", "saturday"], Weekday::Sat),
+	(&["sun", "sunday"], Weekday::Sun),
+];
+
+const MONTHS: [&[&str]; 12] = [
+	&["jan", "january"],
+	&["feb", "february"],
+	&["mar", "march"],
+	&["apr", "april"],
+	&["may"],
+	&["jun", "june"],
+	&["jul", "july"],
+	&["aug", "august"],
+	&["sep", "sept", "september"],
+	&["oct", "october"],
+	&["nov", "november"],
+	&["dec", "december"],
+];
+
+// when something typed like "tomorrow 5pm", "next fri", "in 3 days", "mon 23:59" or "dec 3" is due,
+// relative to now
+// an empty field (or "none") is no due date, and a date without a time gets default_time
+// a time without a date is today, or tomorrow if that time has passed already
+pub fn parse_due(input: &str, now: DateTime<Local>, default_time: NaiveTime) -> Result<Option<DateTime<Local>>, String> {
+	let input = input.trim().to_lowercase();
+	if input.is_empty() || input == "none" || input == "someday" {
+		return Ok(None);
+	}
+
+	let tokens = input.replace(',', " ");
+	let tokens = tokens.split_whitespace().collect::<Vec<&str>>();
+	let today = now.date_naive();
+	let mut date = None;
+	let mut time = None;
+	let mut i = 0;
+	while i < tokens.len() {
+		let (part, used) = parse_part(&tokens[i..], today)?;
+		match part {
+			Part::Date(_) if date.is_some() => return Err("There's more than one date in it".to_string()),
+			Part::Time(_) if time.is_some() => return Err("There's more than one time in it".to_string()),
+			Part::Date(found) => date = Some(found),
+			Part::Time(found) => time = Some(found),
+			Part::Filler => (),
+		}
+		i += used;
+	}
+
+	let (date, time) = match (date, time) {
+		(Some(date), time) => (date, time.unwrap_or(default_time)),
+		(None, Some(time)) if today.and_time(time) > now.naive_local() => (today, time),
+		(None, Some(time)) => (today.succ_opt().ok_or("That's too far ahead")?, time),
+		(None, None) => return Err("There's no date or time in it".to_string()),
+	};
+	Local.from_local_datetime(&date.and_time(time)).earliest()
+		.map(Some)
+		.ok_or_else(|| format!("{} {} doesn't exist in the local time zone", date, time.format("%H:%M")))
+}
+
+// reads whatever starts tokens, and how many tokens it took
+fn parse_part(tokens: &[&str], today: NaiveDate) -> Result<(Part, usize), String> {
+	let word = tokens[0];
+	let next = tokens.get(1).copied();
+	let too_far = || "That's too far ahead".to_string();
+
+	if matches!(word, "at" | "on" | "by" | "due") {
+		return Ok((Part::Filler, 1));
+	}
+	match word {
+		"today" => return Ok((Part::Date(today), 1)),
+		"tomorrow" | "tmr" | "tmrw" => return Ok((Part::Date(today.succ_opt().ok_or_else(too_far)?), 1)),
+		"noon" => return Ok((Part::Time(NaiveTime::from_hms_opt(12, 0, 0).unwrap()), 1)),
+		// the end of the day, rather than its start
+		"midnight" => return Ok((Part::Time(NaiveTime::from_hms_opt(23, 59, 0).unwrap()), 1)),
+		_ => (),
+	}
+
+	// "next" is a week later than without it
+	if word == "next" {
+		let date = match next {
+			Some("week") => today.checked_add_days(Days::new(7)),
+			Some("month") => today.checked_add_months(Months::new(1)),
+			Some(day) => match weekday(day) {
+				Some(weekday) => upcoming(today, weekday).checked_add_days(Days::new(7)),
+				None => return Err(format!("\"next {}\" isn't a day (try \"next fri\", \"next week\")", day)),
+			},
+			None => return Err("\"next\" needs a day, week or month after it".to_string()),
+		};
+		return Ok((Part::Date(date.ok_or_else(too_far)?), 2));
+	}
+	if let Some(weekday) = weekday(word) {
+		return Ok((Part::Date(upcoming(today, weekday)), 1));
+	}
+
+	// "in 3 days", "in a week", "in 2w", "+3d"
+	if word == "in" {
+		let Some(amount) = next else {
+			return Err("\"in\" needs an amount of time after it, like \"in 3 days\"".to_string());
+		};
+		if let Some(date) = offset(amount, "", today) {
+			return Ok((Part::Date(date.ok_or_else(too_far)?), 2));
+		}
+		let count = match amount {
+			"a" | "an" | "one" => Some(1),
+			_ => amount.parse::<u32>().ok(),
+		};
+		let unit = tokens.get(2).copied().unwrap_or("");
+		return match count.and_then(|count| offset(&count.to_string(), unit, today)) {
+			Some(date) => Ok((Part::Date(date.ok_or_else(too_far)?), 3)),
+			None => Err(format!("\"{}\" isn't an amount of time, like \"in 3 days\"", tokens[..tokens.len().min(3)].join(" "))),
+		};
+	}
+	if let Some(date) = word.strip_prefix('+').and_then(|amount| offset(amount, "", today)) {
+		return Ok((Part::Date(date.ok_or_else(too_far)?), 1));
+	}
+
+	// "dec 3", "december 3rd 2025", "3 dec"
+	if let Some(month) = month(word) {
+		let Some(day) = next.and_then(day_of_month) else {
+			return Err(format!("\"{}\" needs a day after it, like \"{} 3\"", word, word));
+		};
+		let year = tokens.get(2).and_then(|year| year.parse::<i32>().ok()).filter(|year| *year >= 1000);
+		let date = calendar_date(today, year, month, day)?;
+		return Ok((Part::Date(date), if year.is_some() { 3 } else { 2 }));
+	}
+	if let (Some(day), Some(month)) = (day_of_month(word), next.and_then(month)) {
+		let year = tokens.get(2).and_then(|year| year.parse::<i32>().ok()).filter(|year| *year >= 1000);
+		let date = calendar_date(today, year, month, day)?;
+		return Ok((Part::Date(date), if year.is_some() { 3 } else { 2 }));
+	}
+
+	if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+		return Ok((Part::Date(date), 1));
+	}
+
+	// "5pm", "5:30 pm", "17:00"
+	if let Some(meridiem @ ("am" | "pm")) = next {
+		if let Some(time) = time_of_day(&format!("{}{}", word, meridiem)) {
+			return Ok((Part::Time(time), 2));
+		}
+	}
+	if let Some(time) = time_of_day(word) {
+		return Ok((Part::Time(time), 1));
+	}
+	if word.contains(':') || word.ends_with("am") || word.ends_with("pm") {
+		return Err(format!("\"{}\" isn't a time, like \"5pm\" or \"17:00\"", word));
+	}
+
+	Err(format!("Don't know what \"{}\" means", word))
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+	WEEKDAYS.iter()
+		.find(|(names, _)| names.contains(&word))
+		.map(|(_, weekday)| *weekday)
+}
+
+// today if it's that day already
+fn upcoming(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+	let days = (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+	today + Days::new(days as u64)
+}
+
+// 1 to 12
+fn month(word: &str) -> Option<u32> {
+	MONTHS.iter()
+		.position(|names| names.contains(&word))
+		.map(|i| i as u32 + 1)
+}
+
+// "3", "3rd"
+fn day_of_month(word: &str) -> Option<u32> {
+	let number = ["st", "nd", "rd", "th"].iter()
+		.find_map(|suffix| word.strip_suffix(suffix))
+		.unwrap_or(word);
+	number.parse::<u32>().ok()
+		.filter(|day| (1..=31).contains(day))
+}
+
+// without a year, the next time that date comes around
+fn calendar_date(today: NaiveDate, year: Option<i32>, month: u32, day: u32) -> Result<NaiveDate, String> {
+	let missing = || {
+		let name = NaiveDate::from_ymd_opt(2000, month, 1).unwrap().format("%B");
+		format!("{} {} doesn't exist", name, day)
+	};
+	match year {
+		Some(year) => NaiveDate::from_ymd_opt(year, month, day).ok_or_else(missing),
+		None => {
+			// February 29th skips ahead to the next leap year
+			(today.year()..today.year() + 8)
+				.filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+				.find(|date| *date >= today)
+				.ok_or_else(missing)
+		},
+	}
+}
+
+// amount being "3d" or "2w" with no unit, or a number with unit being "days", "weeks" or "months"
+// None if it isn't an amount of time at all, Some(None) if it's too far ahead
+fn offset(amount: &str, unit: &str, today: NaiveDate) -> Option<Option<NaiveDate>> {
+	let (count, unit) = if unit.is_empty() {
+		let split = amount.find(|c: char| !c.is_ascii_digit())?;
+		(&amount[..split], &amount[split..])
+	}
+	else {
+		(amount, unit)
+	};
+	let count = count.parse::<u32>().ok()?;
+	match unit {
+		"d" | "day" | "days" => Some(today.checked_add_days(Days::new(count as u64))),
+		"w" | "week" | "weeks" => Some(today.checked_add_days(Days::new(count as u64 * 7))),
+		"m" | "month" | "months" => Some(today.checked_add_months(Months::new(count))),
+		_ => None,
+	}
+}
+
+// "5pm", "5:30pm", "12am", "17:00"
+fn time_of_day(word: &str) -> Option<NaiveTime> {
+	let (clock, meridiem) = match (word.strip_suffix("am"), word.strip_suffix("pm")) {
+		(Some(clock), _) => (clock, Some(0)),
+		(_, Some(clock)) => (clock, Some(12)),
+		_ => (word, None),
+	};
+	let (hour, minute) = match clock.split_once(':') {
+		Some((hour, minute)) if minute.len() == 2 => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+		Some(_) => return None,
+		// a bare number could be a day as well, so it needs the am or pm
+		None if meridiem.is_some() => (clock.parse::<u32>().ok()?, 0),
+		None => return None,
+	};
+	let hour = match meridiem {
+		Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+		Some(_) => return None,
+		None => hour,
+	};
+	NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testutil::{at, now};
+
+	// with assignments due at 8 by default
+	fn parse(input: &str) -> Result<Option<DateTime<Local>>, String> {
+		parse_due(input, now(), NaiveTime::from_hms_opt(8, 0, 0).unwrap())
+	}
+
+	#[test]
+	fn relative_days() {
+		assert_eq!(parse("tomorrow 5pm"), Ok(Some(at(2026, 10, 19, 17, 0))));
+		assert_eq!(parse("in 3 days"), Ok(Some(at(2026, 10, 21, 8, 0))));
+		assert_eq!(parse("+2w"), Ok(Some(at(2026, 11, 1, 8, 0))));
+	}
+
+	#[test]
+	fn weekdays() {
+		assert_eq!(parse("fri"), Ok(Some(at(2026, 10, 23, 8, 0))));
+		assert_eq!(parse("next fri"), Ok(Some(at(2026, 10, 30, 8, 0))));
+		assert_eq!(parse("mon 23:59"), Ok(Some(at(2026, 10, 19, 23, 59))));
+	}
+
+	#[test]
+	fn calendar_dates() {
+		assert_eq!(parse("dec 3"), Ok(Some(at(2026, 12, 3, 8, 0))));
+		// the next February 29th is in 2028
+		assert_eq!(parse("feb 29"), Ok(Some(at(2028, 2, 29, 8, 0))));
+		assert!(parse("feb 30").is_err());
+	}
+
+	#[test]
+	fn times() {
+		assert_eq!(parse("12am tomorrow"), Ok(Some(at(2026, 10, 19, 0, 0))));
+		assert_eq!(parse("7pm"), Ok(Some(at(2026, 10, 18, 19, 0))));
+		// already passed today, so it's tomorrow
+		assert_eq!(parse("5pm"), Ok(Some(at(2026, 10, 19, 17, 0))));
+		assert_eq!(parse("12am"), Ok(Some(at(2026, 10, 19, 0, 0))));
+		assert!(parse("13pm").is_err());
+		assert!(parse("25:00").is_err());
+	}
+
+	#[test]
+	fn nothing_or_nonsense() {
+		assert_eq!(parse(""), Ok(None));
+		assert_eq!(parse("none"), Ok(None));
+		assert!(parse("tomorrow fri").is_err());
+		assert!(parse("5pm 6pm").is_err());
+		assert!(parse("whenever").is_err());
+	}
+}
@@ -0,0 +1,297 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use chrono::{prelude::*, Days, NaiveDate};
+
+use crate::assignment::Assignment;
+use crate::todolist::TodoList;
+
+// a query like `class:physics due<+7d !done tag:exam`, every term of which has to hold
+// terms can be negated with a leading ! and values with spaces go in double quotes
+//   class:TEXT       the class name contains TEXT
+//   name:TEXT, TEXT  the assignment name contains TEXT
+//   tag:TAG          the assignment has TAG
+//   done             it's completed
+//   overdue          it's past due and not completed
+//   due:none         it has no due date
+//   due<DATE         compared with <, <=, =, >= or >, DATE being YYYY-MM-DD,
+//                    today, tomorrow, yesterday or a number of days or weeks from today (+7d, -2w)
+pub struct Filter {
+	terms: Vec<Term>,
+}
+
+struct Term {
+	negated: bool,
+	test: Test,
+}
+
+enum Test {
+	Class(String),
+	Name(String),
+	Tag(String),
+	Done,
+	Overdue,
+	NoDue,
+	Due(Comparison, DateSpec),
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+	Less,
+	LessEq,
+	Eq,
+	GreaterEq,
+	Greater,
+}
+
+// dates relative to today are worked out when the filter is used, so saved filters keep up
+enum DateSpec {
+	Date(NaiveDate),
+	FromToday(i64),
+}
+
+impl DateSpec {
+	fn parse(spec: &str) -> Result<Self, String> {
+		match spec {
+			"today" => return Ok(DateSpec::FromToday(0)),
+			"tomorrow" => return Ok(DateSpec::FromToday(1)),
+			"yesterday" => return Ok(DateSpec::FromToday(-1)),
+			_ => (),
+		}
+		if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+			return Ok(DateSpec::Date(date));
+		}
+
+		let bad = || format!("\"{}\" isn't a date (YYYY-MM-DD, today, +7d, -2w, ...)", spec);
+		let (count, days_per) = if let Some(count) = spec.strip_suffix('d') {
+			(count, 1)
+		}
+		else if let Some(count) = spec.strip_suffix('w') {
+			(count, 7)
+		}
+		else {
+			return Err(bad());
+		};
+		count.parse::<i64>().ok()
+			.and_then(|count| count.checked_mul(days_per))
+			.map(DateSpec::FromToday)
+			.ok_or_else(bad)
+	}
+
+	fn resolve(&self, today: NaiveDate) -> Option<NaiveDate> {
+		match self {
+			DateSpec::Date(date) => Some(*date),
+			DateSpec::FromToday(days) if *days < 0 => today.checked_sub_days(Days::new(days.unsigned_abs())),
+			DateSpec::FromToday(days) => today.checked_add_days(Days::new(*days as u64)),
+		}
+	}
+}
+
+impl Filter {
+	pub fn parse(query: &str) -> Result<Self, String> {
+		let terms = tokenize(query)?
+			.iter()
+			.map(|token| parse_term(token))
+			.collect::<Result<Vec<Term>, String>>()?;
+		Ok(Self { terms })
+	}
+
+	pub fn matches(&self, classname: &str, assign: &Assignment, completed: bool, now: DateTime<Local>) -> bool {
+		self.terms.iter()
+			.all(|term| term.test.matches(classname, assign, completed, now) != term.negated)
+	}
+
+	// every assignment in the list that matches, sorted by due date
+	pub fn select(&self, todolist: &TodoList) -> Vec<(String, Assignment)> {
+		let now = Local::now();
+		let mut selected = vec![];
+		for (classname, assignments) in todolist.snapshot() {
+			for (assign, completed) in assignments.into_values() {
+				if self.matches(&classname, &assign, completed, now) {
+					selected.push((classname.clone(), assign));
+				}
+			}
+		}
+		selected.sort_by(|(ca, a), (cb, b)| (a, ca).cmp(&(b, cb)));
+		selected
+	}
+}
+
+impl Test {
+	fn matches(&self, classname: &str, assign: &Assignment, completed: bool, now: DateTime<Local>) -> bool {
+		match self {
+			Test::Class(text) => classname.to_lowercase().contains(text),
+			Test::Name(text) => assign.name.to_lowercase().contains(text),
+			Test::Tag(tag) => assign.tags.contains(tag),
+			Test::Done => completed,
+			Test::Overdue => !completed && assign.is_past(now),
+			Test::NoDue => assign.due_date.is_none(),
+			Test::Due(comparison, spec) => {
+				let (Some(due_date), Some(date)) = (assign.due_date, spec.resolve(now.date_naive())) else {
+					return false;
+				};
+				let due_date = due_date.date_naive();
+				match comparison {
+					Comparison::Less => due_date < date,
+					Comparison::LessEq => due_date <= date,
+					Comparison::Eq => due_date == date,
+					Comparison::GreaterEq => due_date >= date,
+					Comparison::Greater => due_date > date,
+				}
+			},
+		}
+	}
+}
+
+// splits on whitespace, except inside double quotes
+fn tokenize(query: &str) -> Result<Vec<String>, String> {
+	let mut tokens = vec![];
+	let mut token = String::new();
+	let mut quoted = false;
+	for c in query.chars() {
+		match c {
+			'"' => quoted = !quoted,
+			_ if c.is_whitespace() && !quoted => {
+				if !token.is_empty() {
+					tokens.push(std::mem::take(&mut token));
+				}
+			},
+			_ => token.push(c),
+		}
+	}
+	if quoted {
+		return Err("There's a \" without a closing one".to_string());
+	}
+	if !token.is_empty() {
+		tokens.push(token);
+	}
+	Ok(tokens)
+}
+
+fn parse_term(token: &str) -> Result<Term, String> {
+	let (negated, token) = match token.strip_prefix('!') {
+		Some(token) => (true, token),
+		None => (false, token),
+	};
+	let lower = token.to_lowercase();
+
+	let test = if lower == "done" {
+		Test::Done
+	}
+	else if lower == "overdue" {
+		Test::Overdue
+	}
+	else if lower == "due:none" {
+		Test::NoDue
+	}
+	else if let Some(rest) = lower.strip_prefix("due") {
+		// the two character ones have to be tried first
+		let comparisons = [
+			("<=", Comparison::LessEq), (">=", Comparison::GreaterEq),
+			("<", Comparison::Less), (">", Comparison::Greater),
+			("=", Comparison::Eq), (":", Comparison::Eq),
+		];
+		let (spec, comparison) = comparisons.iter()
+			.find_map(|(op, comparison)| rest.strip_prefix(op).map(|spec| (spec, *comparison)))
+			.ok_or_else(|| format!("\"{}\" needs a comparison, like due<+7d", token))?;
+		Test::Due(comparison, DateSpec::parse(spec)?)
+	}
+	else if let Some((key, value)) = lower.split_once(':') {
+		if value.is_empty() {
+			return Err(format!("\"{}\" needs something after the colon", token));
+		}
+		match key {
+			"class" => Test::Class(value.to_string()),
+			"name" => Test::Name(value.to_string()),
+			"tag" => Test::Tag(value.to_string()),
+			_ => return Err(format!("Unknown filter \"{}:\"", key)),
+		}
+	}
+	else if lower.is_empty() {
+		return Err("A lone ! needs something to negate".to_string());
+	}
+	else {
+		Test::Name(lower)
+	};
+
+	Ok(Term { negated, test })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testutil::{assignment, now};
+
+	fn matches(query: &str, classname: &str, assign: &Assignment, completed: bool) -> bool {
+		Filter::parse(query).unwrap().matches(classname, assign, completed, now())
+	}
+
+	#[test]
+	fn due_comparisons() {
+		let in_week = assignment("Lab 3", Some(7), "");
+		let in_two_weeks = assignment("Lab 4", Some(14), "");
+		assert!(matches("due<=+7d", "Physics", &in_week, false));
+		assert!(!matches("due<+7d", "Physics", &in_week, false));
+		assert!(!matches("due<=+7d", "Physics", &in_two_weeks, false));
+		assert!(matches("due>=+2w", "Physics", &in_two_weeks, false));
+		assert!(matches("due=2026-10-25", "Physics", &in_week, false));
+		assert!(matches("due:2026-10-25", "Physics", &in_week, false));
+		assert!(matches("due>yesterday", "Physics", &in_week, false));
+		// nothing without a due date compares with a date
+		assert!(!matches("due<=+7d", "Physics", &assignment("Reading", None, ""), false));
+		assert!(matches("due:none", "Physics", &assignment("Reading", None, ""), false));
+	}
+
+	#[test]
+	fn negation_and_combination() {
+		let assign = assignment("Lab 3", Some(7), "exam");
+		assert!(matches("!done", "Physics", &assign, false));
+		assert!(!matches("!done", "Physics", &assign, true));
+		assert!(matches("class:phys tag:exam !done due<=+7d", "Physics", &assign, false));
+		assert!(!matches("class:phys !tag:exam", "Physics", &assign, false));
+		assert!(matches("overdue", "Physics", &assignment("Essay", Some(-1), ""), false));
+		assert!(!matches("overdue", "Physics", &assignment("Essay", Some(-1), ""), true));
+		assert!(!matches("overdue", "Physics", &assignment("Essay", Some(1), ""), false));
+	}
+
+	#[test]
+	fn quoted_values() {
+		let assign = assignment("Lab report 3", Some(7), "");
+		assert!(matches("name:\"report 3\"", "Intro Physics", &assign, false));
+		assert!(matches("class:\"intro phys\"", "Intro Physics", &assign, false));
+		// without the quotes it's two terms, the second one a plain name search
+		assert!(!matches("class:intro physics", "Intro Physics", &assign, false));
+		assert!(matches("\"lab report\"", "Intro Physics", &assign, false));
+	}
+
+	#[test]
+	fn errors() {
+		assert!(Filter::parse("name:\"report").is_err());
+		assert!(Filter::parse("due<next week").is_err());
+		assert!(Filter::parse("due<+7x").is_err());
+		assert!(Filter::parse("due<").is_err());
+		assert!(Filter::parse("due~+7d").is_err());
+		assert!(Filter::parse("due<é").is_err());
+		assert!(Filter::parse("due<+7dé").is_err());
+		assert!(Filter::parse("due<+2000000000000000000w").is_err());
+		assert!(Filter::parse("class:").is_err());
+		assert!(Filter::parse("colour:red").is_err());
+		assert!(Filter::parse("!").is_err());
+		assert!(Filter::parse("").is_ok());
+	}
+}
@@ -0,0 +1,236 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use log;
+
+use crate::config::GitConfig;
+use crate::seccomp_sandbox::seccomp_restrict_command;
+use crate::spawner;
+
+// a git command run in dir, committing as the configured user
+// with seccomp on it gets the script profile, like a provider script
+fn git(dir: &Path, git_config: &GitConfig) -> Command {
+	let mut command = Command::new("git");
+	command.arg("-C").arg(dir)
+		.arg("-c").arg(format!("user.name={}", git_config.name))
+		.arg("-c").arg(format!("user.email={}", git_config.email))
+		.stdin(Stdio::null());
+	seccomp_restrict_command(&mut command);
+	command
+}
+
+fn run<I, S>(mut command: Command, args: I) -> Result<Output, String>
+	where I: IntoIterator<Item = S>, S: AsRef<OsStr>
+{
+	command.args(args);
+	let output = command.output()
+		.map_err(|e| format!("Couldn't run git: {}", e))?;
+	if output.status.success() {
+		Ok(output)
+	}
+	else {
+		// leaving out -C and the user options
+		let args = command.get_args()
+			.skip(6)
+			.map(|arg| arg.to_string_lossy())
+			.collect::<Vec<_>>()
+			.join(" ");
+		Err(format!("git {} failed: {}", args, String::from_utf8_lossy(&output.stderr).trim()))
+	}
+}
+
+// makes dir a git repository if it isn't one yet, and points git at our merge driver
+// this is checked on every start, in case the executable moved, but only what's out of date is written
+pub fn init_repo(dir: &Path, git_config: &GitConfig) -> Result<(), String> {
+	if !dir.join(".git").exists() {
+		log::info!("Creating git repository in {}", dir.display());
+		run(git(dir, git_config), ["init", "-q", "-b", &git_config.branch])?;
+	}
+
+	let exe = env::current_exe()
+		.map_err(|e| format!("Couldn't find the todolist executable: {}", e))?;
+	let driver = format!("'{}' merge-driver %O %A %B", exe.display().to_string().replace('\'', "'\\''"));
+	set_config(dir, git_config, "merge.todolist.name", "todolist list merge")?;
+	set_config(dir, git_config, "merge.todolist.driver", &driver)?;
+
+	// kept in .git/info so they don't end up in the history themselves
+	let info = dir.join(".git").join("info");
+	fs::create_dir_all(&info)
+		.and_then(|()| write_if_changed(&info.join("attributes"), "list merge=todolist\n"))
+		.and_then(|()| write_if_changed(&info.join("exclude"), "*.lock\n*.tmp\n*.history\n*.caldav\n*.ics\n"))
+		.map_err(|e| format!("Couldn't set up {}: {}", info.display(), e))?;
+
+	Ok(())
+}
+
+fn set_config(dir: &Path, git_config: &GitConfig, key: &str, value: &str) -> Result<(), String> {
+	// --get fails if the key isn't set
+	let current = run(git(dir, git_config), ["config", "--get", key])
+		.map(|output| String::from_utf8_lossy(&output.stdout).trim_end_matches('\n').to_string());
+	if current.as_deref() != Ok(value) {
+		run(git(dir, git_config), ["config", key, value])?;
+	}
+	Ok(())
+}
+
+fn write_if_changed(path: &Path, contents: &str) -> std::io::Result<()> {
+	match fs::read_to_string(path) {
+		Ok(current) if current == contents => Ok(()),
+		_ => fs::write(path, contents),
+	}
+}
+
+// the message for a commit of these changes, oldest first
+fn commit_message(changes: &[String]) -> String {
+	match changes {
+		[] => "save".to_string(),
+		[change] => change.clone(),
+		changes => format!("{} changes\n\n{}", changes.len(), changes.join("\n")),
+	}
+}
+
+// a list file and the changes saved to it
+type Saved = (PathBuf, Vec<String>);
+
+// the thread committing saves in the background, and how to reach it
+// None until commit_in_background is called, everything is committed right away until then
+static COMMITTER: Mutex<Option<(Sender<Saved>, JoinHandle<()>)>> = Mutex::new(None);
+
+// commits from now on happen on their own thread, so saving doesn't wait for git
+// saves that come in while a commit runs go into the next one together
+pub fn commit_in_background(git_config: &'static GitConfig) {
+	let mut committer = COMMITTER.lock().unwrap();
+	if committer.is_some() {
+		return;
+	}
+
+	let (tx, rx) = mpsc::channel::<Saved>();
+	let handle = thread::spawn(move || {
+		while let Ok(first) = rx.recv() {
+			let mut batch = vec![first];
+			batch.extend(rx.try_iter());
+
+			// in the order they were saved, once per list
+			let mut lists: Vec<Saved> = vec![];
+			for (list_path, changes) in batch {
+				match lists.iter_mut().find(|(path, _)| *path == list_path) {
+					Some((_, all_changes)) => all_changes.extend(changes),
+					None => lists.push((list_path, changes)),
+				}
+			}
+			for (list_path, changes) in lists {
+				if let Err(e) = commit(&list_path, &commit_message(&changes), git_config) {
+					log::warn!("Couldn't commit list: {}", e);
+				}
+			}
+		}
+	});
+	*committer = Some((tx, handle));
+}
+
+// commits the changes saved to list_path, on the committer thread if there is one
+pub fn queue_commit(list_path: &Path, changes: Vec<String>, git_config: &GitConfig) {
+	let changes = match COMMITTER.lock().unwrap().as_ref() {
+		Some((tx, _)) => match tx.send((list_path.to_path_buf(), changes)) {
+			Ok(()) => return,
+			// the changes come back if the thread is gone
+			Err(mpsc::SendError((_, changes))) => changes,
+		},
+		None => changes,
+	};
+	if let Err(e) = commit(list_path, &commit_message(&changes), git_config) {
+		log::warn!("Couldn't commit list: {}", e);
+	}
+}
+
+// waits for the committer thread to commit everything queued, before exiting
+pub fn finish_commits() {
+	let committer = COMMITTER.lock().unwrap().take();
+	if let Some((tx, handle)) = committer {
+		drop(tx);
+		if handle.join().is_err() {
+			log::error!("The committer thread panicked");
+		}
+	}
+}
+
+// commits the list file if it changed since the last commit
+// the global git config is skipped, since the sandbox keeps it out of reach
+// once seccomp is on git is run by the spawner, since this process can't
+pub fn commit(list_path: &Path, message: &str, git_config: &GitConfig) -> Result<(), String> {
+	if let Some(res) = spawner::commit(list_path, message) {
+		return res;
+	}
+
+	let dir = list_path.parent()
+		.ok_or("The list file has no parent directory")?;
+	let file_name = list_path.file_name()
+		.ok_or("The list file has no name")?;
+
+	let mut add = git(dir, git_config);
+	add.env("GIT_CONFIG_GLOBAL", "/dev/null");
+	run(add, [OsStr::new("add"), OsStr::new("--"), file_name])?;
+
+	let mut commit = git(dir, git_config);
+	commit.env("GIT_CONFIG_GLOBAL", "/dev/null");
+	let staged = run(commit, [OsStr::new("diff"), OsStr::new("--cached"), OsStr::new("--quiet"), OsStr::new("--"), file_name]).is_err();
+	if !staged {
+		return Ok(());
+	}
+
+	let mut commit = git(dir, git_config);
+	commit.env("GIT_CONFIG_GLOBAL", "/dev/null");
+	run(commit, [OsStr::new("commit"), OsStr::new("-q"), OsStr::new("-m"), OsStr::new(message), OsStr::new("--"), file_name])?;
+	log::info!("Committed list: {}", message.lines().next().unwrap_or(""));
+	Ok(())
+}
+
+// commits anything left over, merges in the remote branch and pushes the result
+pub fn sync(dir: &Path, git_config: &GitConfig) -> Result<(), String> {
+	let remote = git_config.remote.as_deref()
+		.ok_or("No git.remote set in the config file")?;
+	let branch = &git_config.branch;
+
+	run(git(dir, git_config), ["add", "-u"])?;
+	if run(git(dir, git_config), ["diff", "--cached", "--quiet"]).is_err() {
+		run(git(dir, git_config), ["commit", "-q", "-m", "sync: local changes"])?;
+	}
+
+	// an empty remote has nothing to merge yet
+	let has_branch = git(dir, git_config)
+		.args(["ls-remote", "--exit-code", "--heads", remote, branch])
+		.stdout(Stdio::null())
+		.status()
+		.map_err(|e| format!("Couldn't run git: {}", e))?
+		.success();
+	if has_branch {
+		run(git(dir, git_config), ["fetch", "-q", remote, branch])?;
+		run(git(dir, git_config), ["merge", "-q", "--no-edit", "--allow-unrelated-histories", "-m", "sync: merge remote changes", "FETCH_HEAD"])?;
+	}
+
+	run(git(dir, git_config), ["push", "-q", remote, &format!("HEAD:refs/heads/{}", branch)])?;
+	Ok(())
+}
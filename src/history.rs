@@ -0,0 +1,154 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use log;
+
+use crate::assignment::Assignment;
+
+// one mutation of a TodoList, with enough information to undo it
+// assignments are stored whole instead of by uid, since uids change when they're edited
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Change {
+	CreateClass {
+		classname: String,
+	},
+	DeleteClass {
+		classname: String,
+		assignments: Vec<(Assignment, bool)>,
+	},
+	CreateAssignment {
+		classname: String,
+		assignment: Assignment,
+	},
+	SetCompletion {
+		assignment: Assignment,
+		completed: bool,
+	},
+	EditAssignment {
+		before: Assignment,
+		after: Assignment,
+	},
+	DeleteAssignment {
+		classname: String,
+		assignment: Assignment,
+		completed: bool,
+	},
+}
+
+impl Change {
+	// a short description for the UI, like "create class Physics"
+	pub fn describe(&self) -> String {
+		match self {
+			Change::CreateClass { classname } => format!("create class {}", classname),
+			Change::DeleteClass { classname, .. } => format!("delete class {}", classname),
+			Change::CreateAssignment { assignment, .. } => format!("create {}", assignment.name),
+			Change::SetCompletion { assignment, completed: true } => format!("complete {}", assignment.name),
+			Change::SetCompletion { assignment, completed: false } => format!("uncomplete {}", assignment.name),
+			Change::EditAssignment { before, .. } => format!("edit {}", before.name),
+			Change::DeleteAssignment { assignment, .. } => format!("delete {}", assignment.name),
+		}
+	}
+}
+
+// undo and redo stacks, the undo stack dropping its oldest changes past limit
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct History {
+	undo: VecDeque<Change>,
+	redo: Vec<Change>,
+	#[serde(skip)]
+	limit: usize,
+}
+
+impl History {
+	pub fn new(limit: usize) -> Self {
+		Self {
+			undo: VecDeque::new(),
+			redo: Vec::new(),
+			limit,
+		}
+	}
+
+	// reads a history saved with save, starting fresh if there's none or it's broken
+	pub fn load(path: &Path, limit: usize) -> Self {
+		let history = fs::read_to_string(path).ok()
+			.and_then(|history_str| serde_json::from_str::<History>(&history_str).ok());
+		match history {
+			Some(mut history) => {
+				history.limit = limit;
+				history.trim();
+				history
+			},
+			None => Self::new(limit),
+		}
+	}
+
+	pub fn save(&self, path: &Path) {
+		let json = serde_json::to_string(self).unwrap();
+		if let Err(e) = fs::write(path, json) {
+			log::warn!("Couldn't save undo history to {}: {}", path.display(), e);
+		}
+	}
+
+	// a new change makes everything that was undone unreachable
+	pub fn record(&mut self, change: Change) {
+		self.redo.clear();
+		self.undo.push_back(change);
+		self.trim();
+	}
+
+	pub fn pop_undo(&mut self) -> Option<Change> {
+		self.undo.pop_back()
+	}
+
+	pub fn pop_redo(&mut self) -> Option<Change> {
+		self.redo.pop()
+	}
+
+	pub fn peek_undo(&self) -> Option<&Change> {
+		self.undo.back()
+	}
+
+	pub fn peek_redo(&self) -> Option<&Change> {
+		self.redo.last()
+	}
+
+	pub fn push_undo(&mut self, change: Change) {
+		self.undo.push_back(change);
+		self.trim();
+	}
+
+	pub fn push_redo(&mut self, change: Change) {
+		self.redo.push(change);
+	}
+
+	// for when the list was replaced from outside and the changes may not apply anymore
+	pub fn clear(&mut self) {
+		self.undo.clear();
+		self.redo.clear();
+	}
+
+	fn trim(&mut self) {
+		while self.undo.len() > self.limit {
+			self.undo.pop_front();
+		}
+	}
+}
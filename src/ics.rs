@@ -0,0 +1,129 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt;
+
+use crate::assignment::Assignment;
+use crate::caldav::{Task, Todo, calendar, class_of_categories, parse_vtodo, split_property, unfold, vtodo_lines};
+use crate::config::config;
+use crate::todolist::TodoList;
+
+#[derive(Default)]
+pub struct ImportReport {
+	pub added: usize,
+	// already in the list
+	pub skipped: usize,
+}
+
+impl fmt::Display for ImportReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Added {} assignment(s)", self.added)?;
+		if self.skipped > 0 {
+			write!(f, ", skipped {} that were there already", self.skipped)?;
+		}
+		Ok(())
+	}
+}
+
+// the whole list as one calendar of VTODOs, for other applications
+// each one has its class as the first category and its tags as the rest
+pub fn export_ics(todolist: &TodoList) -> String {
+	let mut components = vec![];
+	for (class, assignments) in todolist.snapshot() {
+		for (assign, completed) in assignments.into_values() {
+			let uid = TodoList::hash_assignment(&assign);
+			let task = Task::new(class.clone(), assign.name.clone(), assign.due_date, completed);
+			let mut categories = vec![class.clone()];
+			categories.extend(assign.tags.iter().cloned());
+			components.extend(vtodo_lines(&format!("{:016x}@todolist", uid), &task, Some(&categories)));
+		}
+	}
+	calendar(components)
+}
+
+// adds every VTODO in a calendar, the class going by the categories like in the shared CalDAV collection
+// what's left of the categories become tags
+pub fn import_ics(todolist: &mut TodoList, ics: &str) -> Result<ImportReport, String> {
+	let todos = split_vtodos(ics).iter()
+		.filter_map(|todo| parse_vtodo(todo))
+		.collect::<Vec<Todo>>();
+	if todos.is_empty() {
+		return Err("There are no tasks in it".to_string());
+	}
+
+	let mut report = ImportReport::default();
+	for todo in todos {
+		if todo.summary.is_empty() {
+			continue;
+		}
+		let class = class_of_categories(&todo.categories, &todolist.get_classes(), &config().caldav);
+		let tags = todo.categories.iter()
+			.filter(|category| **category != class)
+			.map(|category| category.replace(' ', "-"))
+			.collect::<Vec<String>>();
+		// it's fine if the class is there already
+		let _ = todolist.create_class(class.clone());
+		let assignment = Assignment {
+			due_date: todo.due,
+			name: todo.summary,
+			source: None,
+			tags: Assignment::parse_tags(&tags.join(" ")),
+			id: 0,
+		};
+		match todolist.create_assignment(class, assignment) {
+			Ok(uid) => {
+				if todo.completed {
+					let _ = todolist.set_assignment_completion(uid, true);
+				}
+				report.added += 1;
+			},
+			Err(()) => report.skipped += 1,
+		}
+	}
+	Ok(report)
+}
+
+// each VTODO in a calendar on its own, as parse_vtodo only reads the first
+fn split_vtodos(ics: &str) -> Vec<String> {
+	let mut todos = vec![];
+	let mut current: Option<String> = None;
+	let mut depth = 0;
+	for line in unfold(ics) {
+		let (name, value) = match split_property(&line) {
+			Some((name, _params, value)) => (name, value.to_string()),
+			None => (String::new(), String::new()),
+		};
+		if name == "BEGIN" && value.eq_ignore_ascii_case("VTODO") && depth == 0 {
+			current = Some(String::new());
+		}
+		let Some(todo) = current.as_mut() else {
+			continue;
+		};
+		todo.push_str(&line);
+		todo.push('\n');
+		match name.as_str() {
+			"BEGIN" => depth += 1,
+			"END" => depth -= 1,
+			_ => (),
+		}
+		if depth == 0 {
+			todos.extend(current.take());
+		}
+	}
+	todos
+}
@@ -0,0 +1,259 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::thread;
+#[cfg(unix)]
+use std::time::Duration;
+use std::path::Path;
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use log;
+
+use crate::assignment::Assignment;
+use crate::config::config;
+use crate::filter::Filter;
+use crate::merge::Resolution;
+use crate::todolist::{SaveError, TodoList};
+
+// something the command line asks of the list
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+	AddClass {
+		name: String,
+	},
+	Add {
+		class: String,
+		name: String,
+		// no date means no due date
+		date: Option<NaiveDate>,
+		// the default due time if there's none
+		time: Option<NaiveTime>,
+		#[serde(default)]
+		tags: Vec<String>,
+	},
+	Complete {
+		class: String,
+		name: String,
+		completed: bool,
+	},
+	List {
+		// a filter, everything is listed without one
+		#[serde(default)]
+		query: Option<String>,
+	},
+}
+
+#[cfg(unix)]
+#[derive(Deserialize)]
+struct Request {
+	profile: String,
+	command: Command,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Reply {
+	Done(String),
+	Failed(String),
+	// the TUI has another profile open, so the list file is fair game
+	OtherProfile,
+}
+
+impl Command {
+	// returns what to tell the user
+	pub fn apply(&self, todolist: &mut TodoList) -> Result<String, String> {
+		match self {
+			Command::AddClass { name } => {
+				todolist.create_class(name.clone())
+					.map_err(|()| format!("There's a class named \"{}\" already", name))?;
+				Ok(format!("Added class \"{}\"", name))
+			},
+			Command::Add { class, name, date, time, tags } => {
+				let due_date = match date {
+					Some(date) => {
+						let time = time.unwrap_or_else(|| config().display.default_due_time());
+						let due_date = Local.from_local_datetime(&date.and_time(time)).earliest()
+							.ok_or_else(|| format!("{} {} doesn't exist in the local time zone", date, time))?;
+						Some(due_date)
+					},
+					None => None,
+				};
+				let assignment = Assignment {
+					due_date,
+					name: name.clone(),
+					source: None,
+					tags: tags.clone(),
+					id: 0,
+				};
+				todolist.create_assignment(class.clone(), assignment)
+					.map_err(|()| format!("There's no class named \"{}\", or it has that assignment already", class))?;
+				Ok(format!("Added \"{}\" to \"{}\"", name, class))
+			},
+			Command::Complete { class, name, completed } => {
+				let uid = todolist.find_assignment(class, name)
+					.ok_or_else(|| format!("There's no assignment \"{}\" in \"{}\"", name, class))?;
+				todolist.set_assignment_completion(uid, *completed)
+					.map_err(|()| format!("Couldn't change \"{}\"", name))?;
+				Ok(format!("Marked \"{}\" as {}", name, if *completed { "done" } else { "not done" }))
+			},
+			Command::List { query } => {
+				let filter = query.as_deref()
+					.map(Filter::parse)
+					.transpose()?;
+				let now = Local::now();
+				let mut lines = vec![];
+				for class in todolist.get_classes() {
+					let mut uids = todolist.get_class_uids(&class).unwrap_or_default();
+					uids.sort_by_key(|uid| todolist.get_assignment(*uid).ok());
+					let mut class_lines = vec![];
+					for uid in uids {
+						let (Ok(assign), Ok(completed)) = (todolist.get_assignment(uid), todolist.get_assignment_completion(uid)) else {
+							continue;
+						};
+						if filter.as_ref().is_some_and(|filter| !filter.matches(&class, &assign, completed, now)) {
+							continue;
+						}
+						let tags = assign.tags.iter()
+							.map(|tag| format!(" #{}", tag))
+							.collect::<String>();
+						class_lines.push(format!("  [{}] {}  {}{}",
+							if completed { "x" } else { " " },
+							assign.due_date.map(|due_date| due_date.format("%Y-%m-%d %H:%M").to_string())
+								.unwrap_or_else(|| "no due date     ".to_string()),
+							assign.name,
+							tags));
+					}
+					// classes with nothing matching are left out when filtering
+					if filter.is_none() || !class_lines.is_empty() {
+						lines.push(class);
+						lines.append(&mut class_lines);
+					}
+				}
+				Ok(lines.join("\n"))
+			},
+		}
+	}
+}
+
+// for when no TUI has the list open: load it, change it and save it
+pub fn apply_to_file(list_path: &Path, scripts_path: &Path, command: &Command) -> Result<String, String> {
+	let mut todolist = TodoList::new(list_path, scripts_path)?;
+	let message = command.apply(&mut todolist)?;
+	if todolist.is_dirty() {
+		let mut res = todolist.save_to_file();
+		// the list changed under us just now, this is the newer change
+		if let Err(SaveError::Conflicts(n)) = res {
+			for i in 0..n {
+				let _ = todolist.resolve_conflict(i, Resolution::Ours);
+			}
+			res = todolist.finish_merge();
+		}
+		res.map_err(|e| format!("Failed to save list: {}", e))?;
+	}
+	Ok(message)
+}
+
+// hands a request to a running TUI, None if there isn't one or it has another profile open
+#[cfg(unix)]
+pub fn send(socket_path: &Path, profile: &str, command: &Command) -> Option<Result<String, String>> {
+	let mut stream = UnixStream::connect(socket_path).ok()?;
+	let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+	let request = serde_json::json!({
+		"profile": profile,
+		"command": command,
+	});
+	let mut line = request.to_string();
+	line.push('\n');
+	if let Err(e) = stream.write_all(line.as_bytes()) {
+		return Some(Err(format!("Couldn't talk to the running todolist: {}", e)));
+	}
+
+	let mut reply = String::new();
+	if let Err(e) = BufReader::new(stream).read_line(&mut reply) {
+		return Some(Err(format!("The running todolist didn't answer: {}", e)));
+	}
+	match serde_json::from_str(&reply) {
+		Ok(Reply::Done(message)) => Some(Ok(message)),
+		Ok(Reply::Failed(e)) => Some(Err(e)),
+		Ok(Reply::OtherProfile) => None,
+		Err(_) => Some(Err("The running todolist gave a garbled answer".to_string())),
+	}
+}
+
+#[cfg(not(unix))]
+pub fn send(_socket_path: &Path, _profile: &str, _command: &Command) -> Option<Result<String, String>> {
+	None
+}
+
+// answers requests from the command line on another thread, one at a time
+// handle gets the profile the request is for, and has to get the list from the UI thread itself
+#[cfg(unix)]
+pub fn listen<F>(socket_path: &Path, handle: F) -> Result<(), String>
+	where F: Fn(String, Command) -> Reply + Send + 'static
+{
+	if socket_path.exists() {
+		if UnixStream::connect(socket_path).is_ok() {
+			return Err(format!("Another todolist is listening on {} already", socket_path.display()));
+		}
+		// left behind by one that didn't get to clean up
+		let _ = fs::remove_file(socket_path);
+	}
+	let listener = UnixListener::bind(socket_path)
+		.map_err(|e| format!("Couldn't listen on {}: {}", socket_path.display(), e))?;
+	log::info!("Listening for commands on {}", socket_path.display());
+
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let Ok(stream) = stream else {
+				continue;
+			};
+			let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+			let mut line = String::new();
+			let mut reader = BufReader::new(&stream);
+			if reader.read_line(&mut line).is_err() {
+				continue;
+			}
+			let reply = match serde_json::from_str::<Request>(&line) {
+				Ok(request) => handle(request.profile, request.command),
+				Err(e) => Reply::Failed(format!("Bad request: {}", e)),
+			};
+
+			let mut reply = serde_json::to_string(&reply).unwrap();
+			reply.push('\n');
+			let _ = (&stream).write_all(reply.as_bytes());
+		}
+	});
+
+	Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen<F>(_socket_path: &Path, _handle: F) -> Result<(), String>
+	where F: Fn(String, Command) -> Reply + Send + 'static
+{
+	Err("Listening for commands is only supported on Unix".to_string())
+}
@@ -0,0 +1,207 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::fmt;
+
+// something a key in [keys] can be bound to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+	Add,
+	Edit,
+	Delete,
+	Toggle,
+	Save,
+	Quit,
+	Help,
+	Undo,
+	Redo,
+	Search,
+	Palette,
+	Menu,
+}
+
+impl Action {
+	// in the order the help lists them
+	pub const ALL: [Action; 12] = [
+		Action::Add, Action::Edit, Action::Delete, Action::Toggle,
+		Action::Undo, Action::Redo, Action::Search,
+		Action::Palette, Action::Menu,
+		Action::Save, Action::Help, Action::Quit,
+	];
+
+	// the key under [keys]
+	pub fn name(&self) -> &'static str {
+		match self {
+			Action::Add => "add",
+			Action::Edit => "edit",
+			Action::Delete => "delete",
+			Action::Toggle => "toggle",
+			Action::Save => "save",
+			Action::Quit => "quit",
+			Action::Help => "help",
+			Action::Undo => "undo",
+			Action::Redo => "redo",
+			Action::Search => "search",
+			Action::Palette => "palette",
+			Action::Menu => "menu",
+		}
+	}
+
+	pub fn description(&self) -> &'static str {
+		match self {
+			Action::Add => "add an assignment to the class",
+			Action::Edit => "edit one of the class' assignments",
+			Action::Delete => "delete one of the class' assignments",
+			Action::Toggle => "check off one of the class' assignments",
+			Action::Save => "save the list",
+			Action::Quit => "save and quit",
+			Action::Help => "show this help",
+			Action::Undo => "undo the last change",
+			Action::Redo => "redo what was undone",
+			Action::Search => "search the list",
+			Action::Palette => "find a command from the menus",
+			Action::Menu => "open the menubar",
+		}
+	}
+
+	// whether it does something with dialogs open too, the rest only work from the main screen and class dialogs
+	pub fn anywhere(&self) -> bool {
+		matches!(self, Action::Undo | Action::Redo | Action::Search)
+	}
+}
+
+// a key as written in the config file: a character, a named key like "space" or "f1",
+// or either of those after "ctrl-" or "alt-"
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Keystroke {
+	Char(char),
+	Ctrl(char),
+	Alt(char),
+	Named(NamedKey),
+	// F1 to F12
+	Function(u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NamedKey {
+	Enter,
+	Tab,
+	Backspace,
+	Esc,
+	Left,
+	Right,
+	Up,
+	Down,
+	Insert,
+	Del,
+	Home,
+	End,
+	PageUp,
+	PageDown,
+}
+
+const NAMED_KEYS: [(&str, NamedKey); 14] = [
+	("enter", NamedKey::Enter),
+	("tab", NamedKey::Tab),
+	("backspace", NamedKey::Backspace),
+	("esc", NamedKey::Esc),
+	("left", NamedKey::Left),
+	("right", NamedKey::Right),
+	("up", NamedKey::Up),
+	("down", NamedKey::Down),
+	("insert", NamedKey::Insert),
+	("delete", NamedKey::Del),
+	("home", NamedKey::Home),
+	("end", NamedKey::End),
+	("pageup", NamedKey::PageUp),
+	("pagedown", NamedKey::PageDown),
+];
+
+impl Keystroke {
+	pub fn parse(key: &str) -> Result<Self, String> {
+		let bad = || format!("\"{}\" isn't a key (like \"a\", \"space\", \"f1\" or \"ctrl-s\")", key);
+		let lower = key.to_lowercase();
+		let mut chars = key.chars();
+		match (chars.next(), chars.next()) {
+			(Some(c), None) => return Ok(Keystroke::Char(c)),
+			(None, _) => return Err(bad()),
+			_ => (),
+		}
+		if lower == "space" {
+			return Ok(Keystroke::Char(' '));
+		}
+
+		// only characters go with ctrl and alt, cursive has no names for the rest
+		for (prefix, make) in [("ctrl-", Keystroke::Ctrl as fn(char) -> Keystroke), ("alt-", Keystroke::Alt)] {
+			if let Some(rest) = lower.strip_prefix(prefix) {
+				let mut chars = rest.chars();
+				return match (chars.next(), chars.next()) {
+					(Some(c), None) => Ok(make(c)),
+					_ => Err(bad()),
+				};
+			}
+		}
+
+		if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+			if (1..=12).contains(&n) {
+				return Ok(Keystroke::Function(n));
+			}
+		}
+		NAMED_KEYS.iter()
+			.find(|(name, _)| *name == lower)
+			.map(|(_, named)| Keystroke::Named(*named))
+			.ok_or_else(bad)
+	}
+}
+
+impl NamedKey {
+	fn label(&self) -> &'static str {
+		match self {
+			NamedKey::Enter => "Enter",
+			NamedKey::Tab => "Tab",
+			NamedKey::Backspace => "Backspace",
+			NamedKey::Esc => "Esc",
+			NamedKey::Left => "Left",
+			NamedKey::Right => "Right",
+			NamedKey::Up => "Up",
+			NamedKey::Down => "Down",
+			NamedKey::Insert => "Insert",
+			NamedKey::Del => "Delete",
+			NamedKey::Home => "Home",
+			NamedKey::End => "End",
+			NamedKey::PageUp => "Page Up",
+			NamedKey::PageDown => "Page Down",
+		}
+	}
+}
+
+// how the help shows it
+impl fmt::Display for Keystroke {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let label = match self {
+			Keystroke::Char(' ') => "Space".to_string(),
+			Keystroke::Char(c) => c.to_string(),
+			Keystroke::Ctrl(c) => format!("Ctrl-{}", c.to_ascii_uppercase()),
+			Keystroke::Alt(c) => format!("Alt-{}", c),
+			Keystroke::Named(named) => named.label().to_string(),
+			Keystroke::Function(n) => format!("F{}", n),
+		};
+		// so it lines up in the help
+		f.pad(&label)
+	}
+}
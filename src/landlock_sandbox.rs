@@ -1,38 +1,237 @@
 #[cfg(target_os = "linux")]
 use landlock::{
-	ABI, Access, AccessFs,
-	Ruleset, RulesetAttr, RulesetCreatedAttr,
-	RulesetStatus, RulesetError,
+	ABI, Access, AccessFs, AccessNet,
+	Ruleset, RulesetAttr, RulesetCreated, RulesetCreatedAttr,
+	RulesetStatus, RulesetError, LandlockStatus,
 	path_beneath_rules,
 };
 
+#[cfg(target_os = "linux")]
+use std::io;
+#[cfg(target_os = "linux")]
+use std::os::unix::process::CommandExt;
+use std::sync::OnceLock;
+
 use log;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::SandboxConfig;
+
+// the newest ABI we know how to use (V5, for ioctl on devices)
+// the landlock crate runs in best-effort mode, so older kernels just get
+// whatever subset of these rights they understand
+#[cfg(target_os = "linux")]
+const TARGET_ABI: ABI = ABI::V5;
 
+// builds a ruleset that only allows read access to read_paths and full access to all_paths
+// if deny_network is set, all TCP binds and connects are denied too (on ABI V4 and newer)
+// paths that don't exist are left out, there's nothing to allow there and landlock refuses them
 #[cfg(target_os = "linux")]
-pub fn landlock_restrict(rw_dirs: &[&PathBuf], r_dirs: &[&PathBuf]) {
-	let abi = ABI::V1;
+fn create_ruleset<R, W>(read_paths: &[R], all_paths: &[W], deny_network: bool) -> Result<RulesetCreated, RulesetError>
+	where R: AsRef<Path>, W: AsRef<Path>
+{
+	let abi = TARGET_ABI;
+	let mut ruleset = Ruleset::default()
+		.handle_access(AccessFs::from_all(abi))?;
+	if deny_network {
+		ruleset = ruleset.handle_access(AccessNet::from_all(abi))?;
+	}
+	ruleset.create()?
+		.add_rules(path_beneath_rules(existing(read_paths), AccessFs::from_read(abi)))?
+		.add_rules(path_beneath_rules(existing(all_paths), AccessFs::from_all(abi)))
+}
+
+#[cfg(target_os = "linux")]
+fn existing<P: AsRef<Path>>(paths: &[P]) -> Vec<&Path> {
+	paths.iter()
+		.map(|path| path.as_ref())
+		.filter(|path| {
+			let exists = path.exists();
+			if !exists {
+				log::debug!("Not adding {} to the landlock ruleset, it doesn't exist", path.display());
+			}
+			exists
+		})
+		.collect()
+}
+
+// what the sandbox ended up enforcing, for the log and "sandbox status"
+pub struct SandboxReport {
+	pub enforcement: String,
+	pub read_paths: Vec<PathBuf>,
+	pub write_paths: Vec<PathBuf>,
+	pub network: bool,
+	pub seccomp: String,
+}
+
+impl SandboxReport {
+	pub fn disabled(reason: &str) -> Self {
+		Self {
+			enforcement: format!("disabled ({})", reason),
+			read_paths: Vec::new(),
+			write_paths: Vec::new(),
+			network: true,
+			seccomp: "off".to_string(),
+		}
+	}
+}
+
+impl fmt::Display for SandboxReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "Landlock: {}", self.enforcement)?;
+		writeln!(f, "Seccomp: {}", self.seccomp)?;
+		writeln!(f, "Network: {}", if self.network { "allowed" } else { "denied" })?;
+		writeln!(f, "Read-only:")?;
+		for path in &self.read_paths {
+			writeln!(f, "  {}", path.display())?;
+		}
+		writeln!(f, "Read-write:")?;
+		for path in &self.write_paths {
+			writeln!(f, "  {}", path.display())?;
+		}
+		Ok(())
+	}
+}
+
+// whether provider scripts get their own sandbox, and the config's extra read paths they get
+// decided once at startup, apart from whether the main process is sandboxed
+static SCRIPT_SANDBOX: OnceLock<(bool, Vec<PathBuf>)> = OnceLock::new();
+
+// has to be called before any provider script runs
+pub fn set_script_sandbox(enabled: bool, read_paths: &[PathBuf]) {
+	let _ = SCRIPT_SANDBOX.set((enabled, read_paths.to_vec()));
+}
+
+// scripts are sandboxed unless told otherwise
+pub fn script_sandbox_enabled() -> bool {
+	SCRIPT_SANDBOX.get().map(|(enabled, _)| *enabled).unwrap_or(true)
+}
+
+// network allows TCP, either because the policy does or because the application needs it
+#[cfg(target_os = "linux")]
+pub fn landlock_restrict(rw_dirs: &[&PathBuf], r_dirs: &[&PathBuf], policy: &SandboxConfig, network: bool) -> SandboxReport {
 	let read_dirs = [
 		"/usr", "/etc", "/dev",
-	];
+	].iter()
+		.map(PathBuf::from)
+		.chain(r_dirs.iter().map(|dir| (*dir).clone()))
+		.chain(policy.read.iter().cloned())
+		.collect::<Vec<PathBuf>>();
 	let all_dirs = [
 		"/dev/tty", "/dev/null",
 		"/tmp",
-	];
-	let status = Ruleset::default()
-		.handle_access(AccessFs::from_all(abi)).unwrap()
-		.create().unwrap()
-		.add_rules(path_beneath_rules(&read_dirs, AccessFs::from_read(abi))).unwrap()
-		.add_rules(path_beneath_rules(r_dirs, AccessFs::from_read(abi))).unwrap()
-		.add_rules(path_beneath_rules(&all_dirs, AccessFs::from_all(abi))).unwrap()
-		.add_rules(path_beneath_rules(rw_dirs, AccessFs::from_all(abi))).unwrap()
-		.restrict_self().unwrap();
-	match status.ruleset {
-		RulesetStatus::FullyEnforced => log::info!("Landlock fully enforced"),
-		RulesetStatus::PartiallyEnforced => log::warn!("Landlock partially enforced"),
-		RulesetStatus::NotEnforced => log::warn!("Landlock unenforced"),
+	].iter()
+		.map(PathBuf::from)
+		.chain(rw_dirs.iter().map(|dir| (*dir).clone()))
+		.chain(policy.write.iter().cloned())
+		.collect::<Vec<PathBuf>>();
+
+	let mut report = SandboxReport {
+		enforcement: String::new(),
+		read_paths: read_dirs.clone(),
+		write_paths: all_dirs.clone(),
+		network,
+		seccomp: "off".to_string(),
+	};
+
+	let status = create_ruleset(&read_dirs, &all_dirs, !network)
+		.and_then(|ruleset| ruleset.restrict_self());
+	let status = match status {
+		Ok(status) => status,
+		Err(e) => {
+			log::error!("Failed to enforce landlock, running unsandboxed: {}", e);
+			report.enforcement = format!("failed ({})", e);
+			report.network = true;
+			return report;
+		},
+	};
+
+	let abi = match status.landlock {
+		LandlockStatus::Available { effective_abi, .. } => {
+			log::info!("Landlock {} available", effective_abi);
+			format!("ABI {}", effective_abi)
+		},
+		LandlockStatus::NotEnabled => {
+			log::warn!("Landlock is supported but not enabled by the kernel");
+			"not enabled by the kernel".to_string()
+		},
+		LandlockStatus::NotImplemented => {
+			log::warn!("Landlock is not implemented by the kernel");
+			"not implemented by the kernel".to_string()
+		},
+	};
+	report.enforcement = match status.ruleset {
+		RulesetStatus::FullyEnforced => {
+			log::info!("Landlock fully enforced");
+			format!("fully enforced, {}", abi)
+		},
+		RulesetStatus::PartiallyEnforced => {
+			log::warn!("Landlock partially enforced");
+			format!("partially enforced, {}", abi)
+		},
+		RulesetStatus::NotEnforced => {
+			log::warn!("Landlock unenforced");
+			format!("not enforced, {}", abi)
+		},
+	};
+	// network rules only exist from ABI V4 on
+	if !matches!(status.landlock, LandlockStatus::Available { effective_abi, .. } if effective_abi >= ABI::V4) {
+		report.network = true;
+	}
+
+	report
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn landlock_restrict(_rw_dirs: &[&PathBuf], _r_dirs: &[&PathBuf], _policy: &SandboxConfig, _network: bool) -> SandboxReport {
+	SandboxReport::disabled("Landlock is only available on Linux")
+}
+
+// puts a child process in its own, tighter landlock domain once it's spawned
+// it only gets read access to the system directories, the config's read paths and r_paths,
+// and read-write access to rw_paths
+// network access can only be allowed if the parent's domain allows it too
+#[cfg(target_os = "linux")]
+pub fn landlock_restrict_command(command: &mut Command, rw_paths: &[&Path], r_paths: &[&Path], network: bool) -> Result<(), ()> {
+	let read_paths = [
+		"/usr", "/etc", "/bin", "/sbin", "/lib", "/lib64",
+	].iter()
+		.map(Path::new)
+		.chain(SCRIPT_SANDBOX.get().into_iter().flat_map(|(_, paths)| paths).map(|path| path.as_path()))
+		.chain(r_paths.iter().copied())
+		.collect::<Vec<&Path>>();
+	let all_paths = [
+		"/dev/null",
+	].iter()
+		.map(Path::new)
+		.chain(rw_paths.iter().copied())
+		.collect::<Vec<&Path>>();
+
+	let mut ruleset = match create_ruleset(&read_paths, &all_paths, !network) {
+		Ok(ruleset) => Some(ruleset),
+		Err(e) => {
+			log::error!("Failed to create landlock ruleset for {:?}: {}", command.get_program(), e);
+			return Err(());
+		},
+	};
+
+	// this runs in the child between fork and exec, so it can't log or allocate
+	unsafe {
+		command.pre_exec(move || {
+			if let Some(ruleset) = ruleset.take() {
+				ruleset.restrict_self()
+					.map_err(|_| io::Error::from(io::ErrorKind::PermissionDenied))?;
+			}
+			Ok(())
+		});
 	}
+
+	Ok(())
 }
 
 #[cfg(not(target_os = "linux"))]
-pub fn landlock_restrict(_rw_dirs: &[&PathBuf], _r_dirs: &[&PathBuf]) {}
+pub fn landlock_restrict_command(_command: &mut Command, _rw_paths: &[&Path], _r_paths: &[&Path], _network: bool) -> Result<(), ()> {
+	Ok(())
+}
@@ -17,79 +17,483 @@ along with this program; if not, see
 */
 
 use std::env;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 use std::cell::RefCell;
 use std::vec::Vec;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::BufWriter;
-use std::fs::{self,File};
+use std::fs::{self,File,OpenOptions};
 use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
 use chrono::{prelude::*, NaiveDateTime, NaiveDate, NaiveTime, Days};
 use cursive::Cursive;
-use cursive::views::{Button, Dialog, DummyView, EditView, TextView, LinearLayout, SelectView, ScrollView, Checkbox};
+use cursive::event::{Event, Key};
+use cursive::menu;
+use cursive::views::{Button, Dialog, DummyView, EditView, TextView, LinearLayout, Menubar, NamedView, OnEventView, SelectView, ScrollView, Checkbox};
 use cursive::traits::*;
+use cursive::theme::{ColorStyle, Effect, Style};
+use cursive::utils::markup::StyledString;
 use simplelog::WriteLogger;
 use log;
 use directories::ProjectDirs;
+use signal_hook::consts::signal::{SIGHUP, SIGTERM};
 
 mod assignment;
 mod todolist;
 mod landlock_sandbox;
+mod provider;
+mod config;
+mod seccomp_sandbox;
+mod spawner;
+mod profile;
+mod history;
+mod merge;
+mod watcher;
+mod git;
+mod caldav;
+mod ics;
+mod server;
+mod ipc;
+mod calendar;
+mod datepicker;
+mod search;
+mod filter;
+mod keys;
+mod due;
+#[cfg(test)]
+mod testutil;
 
 use assignment::Assignment;
-use todolist::TodoList;
-use landlock_sandbox::landlock_restrict;
+use todolist::{TodoList, SaveError};
+use merge::Resolution;
+use watcher::{start_watching, watch_list};
+use git::{commit_in_background, finish_commits, init_repo, sync};
+use server::serve;
+use calendar::MonthView;
+use datepicker::DatePicker;
+use search::{fuzzy_matches, fuzzy_score};
+use filter::Filter;
+use keys::{Action, Keystroke, NamedKey};
+use due::parse_due;
+use ipc::{Command, Reply, apply_to_file, listen, send};
+use landlock_sandbox::{landlock_restrict, set_script_sandbox, SandboxReport};
+use config::{CompletedDisplay, Config, config, expand_home, set_config};
+use seccomp_sandbox::{seccomp_enable, seccomp_restrict};
+use spawner::{serve_spawner, start_spawner};
+use profile::{
+	DEFAULT_PROFILE,
+	create_profile, current_profile, get_profiles, set_current_profile, set_profile_roots, validate_profile_name,
+	profile_list_path, profile_scripts_path,
+};
 
-fn main() {
-	let proj_dirs = ProjectDirs::from("com.ryanrhee", "", "TodoList").unwrap();
-	let logpath = {
-		let mut logpath = PathBuf::new();
-		// because state directory is not available in every platform,
-		// put log file in local data if state is not available
-		if let Some(state) = proj_dirs.state_dir() {
-			fs::create_dir_all(state).unwrap();
-			logpath.push(state);
+// the days the week view shows, None while it follows today
+static WEEK_RANGE: Mutex<Option<(NaiveDate, NaiveDate)>> = Mutex::new(None);
+// how completed assignments are shown, None until it's changed from what the config file says
+static COMPLETED_DISPLAY: Mutex<Option<CompletedDisplay>> = Mutex::new(None);
+// what the week view and class dialogs are narrowed down to, empty for everything
+static SEARCH: Mutex<String> = Mutex::new(String::new());
+// days whose completed assignments were expanded while collapsed
+// None being the assignments without a due date
+static EXPANDED_DAYS: Mutex<BTreeSet<Option<NaiveDate>>> = Mutex::new(BTreeSet::new());
+// saved filters by name, None until one is added or removed since the config file was read
+static SMART_LISTS: Mutex<Option<BTreeMap<String, String>>> = Mutex::new(None);
+// set while a CalDAV sync works on a copy of the list, which can't be changed until it's back
+static SYNCING: AtomicBool = AtomicBool::new(false);
+// whether the sandbox lets this process open TCP connections
+static NETWORK: AtomicBool = AtomicBool::new(true);
+
+const USAGE: &str = "\
+Usage: todolist [--profile NAME] [--no-sandbox] [COMMAND]
+
+Without a command, opens the todo list.
+
+Options:
+  --profile NAME    use the list and scripts of profile NAME
+  --no-sandbox      don't restrict the application with Landlock
+
+Commands:
+  sandbox status    print what the sandbox enforces and exit
+  sync              pull, merge and push the list with the configured git remote
+  caldav sync       sync the list with the configured CalDAV server
+  serve             serve the list over HTTP as JSON
+  add-class NAME    add a class
+  add CLASS NAME [YYYY-MM-DD [HH:MM]] [tag:TAG]...
+                    add an assignment, without a due date if there's no date
+  complete CLASS NAME
+                    mark an assignment done
+  uncomplete CLASS NAME
+                    mark an assignment not done
+  list [QUERY]      print every class and assignment, or only the ones
+                    matching a filter like 'class:physics due<+7d !done'
+
+Changes made from the command line go to the running todolist if there is one
+with the same profile open, and straight to the list file otherwise.";
+
+enum Subcommand {
+	Tui,
+	SandboxStatus,
+	Sync,
+	CaldavSync,
+	Serve,
+	// one-off changes, handed to the running TUI if there is one
+	Cli(Command),
+	// run by git when merging the list, not by people
+	MergeDriver {
+		base: PathBuf,
+		ours: PathBuf,
+		theirs: PathBuf,
+	},
+	// run by todolist itself once seccomp is on, to run git and the provider scripts for it
+	Spawner,
+}
+
+struct Args {
+	no_sandbox: bool,
+	profile: String,
+	subcommand: Subcommand,
+}
+
+fn parse_args() -> Result<Args, String> {
+	let mut no_sandbox = false;
+	let mut profile = DEFAULT_PROFILE.to_string();
+	let mut positional = vec![];
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--no-sandbox" => no_sandbox = true,
+			"--profile" => {
+				profile = args.next()
+					.ok_or("--profile needs a profile name")?;
+				validate_profile_name(&profile)?;
+			},
+			_ if arg.starts_with("-") => return Err(format!("Unknown option \"{}\"", arg)),
+			_ => positional.push(arg),
 		}
-		else {
-			let data = proj_dirs.data_local_dir();
-			fs::create_dir_all(data).unwrap();
-			logpath.push(data);
+	}
+
+	let subcommand = match positional.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+		[] => Subcommand::Tui,
+		["sandbox", "status"] => Subcommand::SandboxStatus,
+		["sync"] => Subcommand::Sync,
+		["caldav", "sync"] => Subcommand::CaldavSync,
+		["serve"] => Subcommand::Serve,
+		["add-class", name] => Subcommand::Cli(Command::AddClass {
+			name: name.to_string(),
+		}),
+		["add", class, name, rest @ ..] if rest.iter().filter(|arg| !arg.starts_with("tag:")).count() <= 2 => {
+			let (tags, due): (Vec<&str>, Vec<&str>) = rest.iter()
+				.copied()
+				.partition(|arg| arg.starts_with("tag:"));
+			Subcommand::Cli(Command::Add {
+				class: class.to_string(),
+				name: name.to_string(),
+				date: due.first()
+					.map(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d")
+						.map_err(|_| format!("\"{}\" isn't a YYYY-MM-DD date", date)))
+					.transpose()?,
+				time: due.get(1)
+					.map(|time| NaiveTime::parse_from_str(time, "%H:%M")
+						.map_err(|_| format!("\"{}\" isn't a HH:MM time", time)))
+					.transpose()?,
+				tags: Assignment::parse_tags(&tags.iter()
+					.map(|tag| tag.trim_start_matches("tag:"))
+					.collect::<Vec<&str>>()
+					.join(" ")),
+			})
+		},
+		[verb @ ("complete" | "uncomplete"), class, name] => Subcommand::Cli(Command::Complete {
+			class: class.to_string(),
+			name: name.to_string(),
+			completed: *verb == "complete",
+		}),
+		["list", query @ ..] => Subcommand::Cli(Command::List {
+			query: (!query.is_empty()).then(|| query.join(" ")),
+		}),
+		["merge-driver", base, ours, theirs] => Subcommand::MergeDriver {
+			base: PathBuf::from(base),
+			ours: PathBuf::from(ours),
+			theirs: PathBuf::from(theirs),
+		},
+		["spawner"] => Subcommand::Spawner,
+		_ => return Err(format!("Unknown command \"{}\"", positional.join(" "))),
+	};
+
+	Ok(Args {
+		no_sandbox,
+		profile,
+		subcommand,
+	})
+}
+
+fn main() {
+	let args = match parse_args() {
+		Ok(args) => args,
+		Err(e) => {
+			eprintln!("{}\n\n{}", e, USAGE);
+			process::exit(2);
+		},
+	};
+
+	if let Subcommand::MergeDriver { base, ours, theirs } = &args.subcommand {
+		match TodoList::merge_files(base, ours, theirs) {
+			Ok(conflicts) => {
+				for conflict in conflicts {
+					eprintln!("Conflict, kept ours: {}", conflict.describe());
+				}
+				process::exit(0);
+			},
+			Err(e) => {
+				eprintln!("{}", e);
+				process::exit(1);
+			},
 		}
-		logpath.push("log");
-		logpath
+	}
+
+	let proj_dirs = ProjectDirs::from("com.ryanrhee", "", "TodoList").unwrap();
+	let configparentpath = PathBuf::from(proj_dirs.config_dir());
+	let configpath = {
+		let mut configpath = configparentpath.clone();
+		configpath.push("config.toml");
+		configpath
+	};
+
+	match Config::load(&configpath) {
+		Ok(config) => set_config(config),
+		Err(e) => {
+			eprintln!("{}", e);
+			process::exit(1);
+		},
+	}
+	let config = config();
+	// before anything runs the provider scripts, the CLI path included
+	set_script_sandbox(!args.no_sandbox && config.sandbox.enabled, &config.sandbox.read);
+
+	let logpath = match &config.paths.log {
+		Some(logpath) => {
+			if let Some(parent) = logpath.parent() {
+				fs::create_dir_all(parent).unwrap();
+			}
+			logpath.clone()
+		},
+		None => {
+			let mut logpath = PathBuf::new();
+			// because state directory is not available in every platform,
+			// put log file in local data if state is not available
+			if let Some(state) = proj_dirs.state_dir() {
+				fs::create_dir_all(state).unwrap();
+				logpath.push(state);
+			}
+			else {
+				let data = proj_dirs.data_local_dir();
+				fs::create_dir_all(data).unwrap();
+				logpath.push(data);
+			}
+			logpath.push("log");
+			logpath
+		},
 	};
 	let listparentpath = {
-		let data = proj_dirs.data_dir();
+		let data = config.paths.data.as_deref()
+			.unwrap_or(proj_dirs.data_dir());
 		fs::create_dir_all(data).unwrap();
 
 		let mut listpath = PathBuf::new();
 		listpath.push(data);
 		listpath
 	};
-	let listpath = {
-		let mut listpath = listparentpath.clone();
-		listpath.push("list");
-		listpath
-	};
-	let scriptspath = {
-		let mut scriptspath = PathBuf::new();
-		scriptspath.push(proj_dirs.config_dir());
-		scriptspath.push("scripts");
+	let scriptsparentpath = {
+		let scriptspath = match &config.paths.scripts {
+			Some(scriptspath) => scriptspath.clone(),
+			None => {
+				let mut scriptspath = configparentpath.clone();
+				scriptspath.push("scripts");
+				scriptspath
+			},
+		};
 
 		fs::create_dir_all(&scriptspath).unwrap();
 
 		scriptspath
 	};
 
-	let _ = File::create_new(&logpath);
-	landlock_restrict(&[&listparentpath, &listpath, &logpath], &[&scriptspath]);
+	set_profile_roots(listparentpath.clone(), scriptsparentpath.clone());
+	if let Err(e) = create_profile(&args.profile) {
+		eprintln!("{}", e);
+		process::exit(1);
+	}
+	set_current_profile(&args.profile);
+	let listpath = profile_list_path(&args.profile);
+	let scriptspath = profile_scripts_path(&args.profile);
 
+	// printing the sandbox status shouldn't touch the repository, and the spawner's application already did
+	if config.git.enabled && !matches!(args.subcommand, Subcommand::SandboxStatus | Subcommand::Spawner) {
+		if let Err(e) = init_repo(&listparentpath, &config.git) {
+			eprintln!("{}", e);
+			process::exit(1);
+		}
+	}
+
+	// this runs outside the sandbox, since git needs the user's own setup to reach the remote
+	if let Subcommand::Sync = args.subcommand {
+		if !config.git.enabled {
+			eprintln!("Syncing needs git.enabled in the config file");
+			process::exit(1);
+		}
+		match sync(&listparentpath, &config.git) {
+			Ok(()) => println!("Synced with {}", config.git.remote.as_deref().unwrap_or("")),
+			Err(e) => {
+				eprintln!("{}", e);
+				process::exit(1);
+			},
+		}
+		return;
+	}
+
+	// so does this, the server being the whole point of it
+	if let Subcommand::CaldavSync = args.subcommand {
+		let mut todolist = match TodoList::new(&listpath, &scriptspath) {
+			Ok(todolist) => todolist,
+			Err(e) => {
+				eprintln!("{}", e);
+				process::exit(1);
+			},
+		};
+		let report = match caldav::sync(&mut todolist, &config.caldav) {
+			Ok(report) => report,
+			Err(e) => {
+				eprintln!("{}", e);
+				process::exit(1);
+			},
+		};
+		println!("{}", report);
+
+		let mut res = todolist.save_to_file();
+		// the list changed while syncing, someone's probably editing it
+		if let Err(SaveError::Conflicts(n)) = res {
+			eprintln!("Keeping the synced side of {} conflict(s) with the list file", n);
+			for i in 0..n {
+				let _ = todolist.resolve_conflict(i, Resolution::Ours);
+			}
+			res = todolist.finish_merge();
+		}
+		if let Err(e) = res {
+			eprintln!("Failed to save list: {}", e);
+			process::exit(1);
+		}
+		if let Err(e) = report.save_mapping(todolist.get_list_path()) {
+			eprintln!("{}", e);
+			process::exit(1);
+		}
+		return;
+	}
+
+	let socketparentpath = proj_dirs.runtime_dir()
+		.unwrap_or(proj_dirs.data_local_dir())
+		.to_path_buf();
+	fs::create_dir_all(&socketparentpath).unwrap();
+	let socketpath = socketparentpath.join("todolist.sock");
+
+	// before the logger, so the log of a running TUI isn't cut short
+	if let Subcommand::Cli(command) = &args.subcommand {
+		let res = send(&socketpath, &args.profile, command)
+			.unwrap_or_else(|| apply_to_file(&listpath, &scriptspath, command));
+		match res {
+			Ok(message) => println!("{}", message),
+			Err(e) => {
+				eprintln!("{}", e);
+				process::exit(1);
+			},
+		}
+		return;
+	}
+
+	// the logger comes first so that sandboxing failures end up in the log
+	// only the TUI starts a fresh log, anything else appends so a running TUI's log is kept
+	let logfile = OpenOptions::new()
+		.create(true)
+		.write(true)
+		.truncate(matches!(args.subcommand, Subcommand::Tui))
+		.append(!matches!(args.subcommand, Subcommand::Tui))
+		.open(&logpath)
+		.unwrap();
 	WriteLogger::init(log::LevelFilter::Debug,
 		simplelog::Config::default(),
-		BufWriter::new(File::create(logpath).unwrap())).unwrap();
+		BufWriter::new(logfile)).unwrap();
 
-	let todolist = Arc::new(RefCell::new(TodoList::new(listpath, scriptspath).unwrap()));
+	// it's already sandboxed by the application that started it
+	if let Subcommand::Spawner = args.subcommand {
+		serve_spawner(&config.git);
+		log::logger().flush();
+		return;
+	}
+
+	// serving is no use without accepting connections
+	let serving = matches!(args.subcommand, Subcommand::Serve);
+	let network = config.sandbox.network || serving;
+	let mut sandbox_report = if args.no_sandbox {
+		log::warn!("Sandbox disabled with --no-sandbox");
+		SandboxReport::disabled("--no-sandbox")
+	}
+	else if !config.sandbox.enabled {
+		log::warn!("Sandbox disabled in the config file");
+		SandboxReport::disabled("turned off in the config file")
+	}
+	else {
+		// settings changed in the UI are saved to it, and it can't be made once sandboxed
+		if !configpath.exists() {
+			let _ = File::create(&configpath);
+		}
+		landlock_restrict(&[&listparentpath, &listpath, &logpath, &socketparentpath, &configpath], &[&configparentpath, &scriptsparentpath], &config.sandbox, network)
+	};
+
+	NETWORK.store(sandbox_report.network, Ordering::Relaxed);
+
+	let mut seccomp = !args.no_sandbox && config.sandbox.enabled && config.sandbox.seccomp;
+	if seccomp {
+		if let Err(e) = seccomp_enable() {
+			log::error!("Failed to set up seccomp: {}", e);
+			sandbox_report.seccomp = format!("failed ({})", e);
+			seccomp = false;
+		}
+	}
+
+	match args.subcommand {
+		Subcommand::Tui | Subcommand::Serve | Subcommand::Sync | Subcommand::CaldavSync | Subcommand::Cli(_) | Subcommand::MergeDriver { .. } | Subcommand::Spawner => (),
+		Subcommand::SandboxStatus => {
+			if seccomp {
+				sandbox_report.seccomp = "enabled, installed once the provider scripts ran".to_string();
+			}
+			print!("{}", sandbox_report);
+			return;
+		},
+	}
+
+	let todolist = TodoList::new(listpath, scriptspath).unwrap();
+
+	// the main profile can't run anything, so git and the provider scripts go through the spawner from here on
+	// without it they couldn't run at all, so the filter stays off then
+	if seccomp {
+		match start_spawner().and_then(|()| seccomp_restrict()) {
+			Ok(()) => log::info!("Seccomp filter installed"),
+			Err(e) => log::error!("Failed to install seccomp filter: {}", e),
+		}
+	}
+
+	if serving {
+		if let Err(e) = serve(todolist, &config.server) {
+			log::error!("{}", e);
+			eprintln!("{}", e);
+			process::exit(1);
+		}
+		return;
+	}
+
+	let todolist = Arc::new(RefCell::new(todolist));
 
 	let mut siv = cursive::default();
 	siv.set_user_data(todolist.clone());
@@ -103,21 +507,27 @@ fn main() {
 			}).with_name("select")
 			.min_size((15, 10));
 
+		let mut smart_view = SelectView::<String>::new();
+		make_smart_list_view(&mut smart_view);
+		let smart_view = smart_view.on_submit(|s, name: &str| {
+				show_smart_list(s, name.to_string())
+			}).with_name("smart_lists");
+
 		let classes_view = LinearLayout::vertical()
 			.child(classes_view)
-			.child(Button::new("Add new class", add_classname));
+			.child(Button::new("Add new class", add_classname))
+			.child(DummyView)
+			.child(TextView::new("Smart lists"))
+			.child(smart_view)
+			.child(Button::new("New smart list", new_smart_list));
 
 		Dialog::around(classes_view)
 			.title("Classes")
 	};
 
-	let week_todo = {
-		let mut vert = LinearLayout::vertical().with_name("weektodo");
-		make_todo_list(&todolist.borrow(), &mut (*vert.get_mut()));
-		let vert = ScrollView::new(vert);
-		Dialog::around(vert)
-			.title("TODO This Week")
-	};
+	let week_todo = Dialog::around(make_week_view(&todolist.borrow()))
+		.title(week_title())
+		.with_name("overview");
 
 	let info_view = LinearLayout::horizontal()
 		.child(classes_view)
@@ -125,78 +535,992 @@ fn main() {
 		.child(week_todo);
 
 	let buttons = LinearLayout::horizontal()
-		.child(Button::new("Save", |s| {
-			{
-				let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
-				todolist.save_to_file();
-			}
-			s.add_layer(Dialog::around(TextView::new("Saved list to file successfully!"))
-				.button("OK", |s| {
-					s.pop_layer();
-				}));
-		}))
+		.child(Button::new("Save", save))
+		.child(DummyView)
+		.child(Button::new("Undo", undo))
+		.child(DummyView)
+		.child(Button::new("Redo", redo))
+		.child(DummyView)
+		.child(Button::new("Profiles", select_profile))
+		.child(DummyView)
+		.child(Button::new("Calendar", toggle_overview).with_name("toggle"))
+		.child(DummyView);
+	let buttons = if config.caldav.is_configured() {
+		buttons.child(Button::new("Sync", caldav_sync))
+			.child(DummyView)
+	}
+	else {
+		buttons
+	};
+	let buttons = buttons
+		.child(Button::new("Quit", Cursive::quit))
 		.child(DummyView)
-		.child(Button::new("Quit", Cursive::quit));
+		.child(TextView::new("").with_name("dirty"));
 
 	siv.add_layer(Dialog::around(LinearLayout::vertical()
-		.child(info_view)
-		.child(DummyView)
-		.child(buttons)));
+			.child(info_view)
+			.child(DummyView)
+			.child(buttons))
+		.title(main_title())
+		.with_name("main"));
 
-	//let main_menu = Menubar::new()
-		//.insert(
+	// the class dialog catches these first, for its own class
+	for action in Action::ALL {
+		siv.add_global_callback(key_event(config.keys.key(action)), move |s| {
+			if SYNCING.load(Ordering::Relaxed) {
+				return;
+			}
+			if action.anywhere() || s.screen().len() == 1 {
+				class_action(s, action);
+			}
+		});
+	}
+
+	// SIGTERM and SIGHUP (the terminal going away) quit cleanly, saving on the way out
+	let quit_signal = Arc::new(AtomicBool::new(false));
+	for signal in [SIGTERM, SIGHUP] {
+		if let Err(e) = signal_hook::flag::register(signal, quit_signal.clone()) {
+			log::error!("Couldn't handle signal {}: {}", signal, e);
+		}
+	}
+
+	// the list can't leave the UI thread, so this one just pokes it every so often
+	let cb_sink = siv.cb_sink().clone();
+	thread::spawn(move || {
+		loop {
+			thread::sleep(Duration::from_millis(500));
+			let quit = quit_signal.load(Ordering::Relaxed);
+			let sent = cb_sink.send(Box::new(move |s: &mut Cursive| {
+				if quit {
+					log::info!("Quitting on signal");
+					s.quit();
+				}
+				else {
+					autosave(s);
+				}
+			}));
+			if sent.is_err() || quit {
+				break;
+			}
+		}
+	});
+
+	// other processes saving the list show up here without waiting for a restart
+	let cb_sink = siv.cb_sink().clone();
+	let watching = start_watching(move || {
+		let _ = cb_sink.send(Box::new(reload_list));
+	}).and_then(|()| watch_list(todolist.borrow().get_list_path()));
+	if let Err(e) = watching {
+		log::warn!("Not watching the list file: {}", e);
+	}
+
+	// changes from the command line are made here, so they show up right away and don't race our saves
+	let cb_sink = siv.cb_sink().clone();
+	let listening = listen(&socketpath, move |profile, command| {
+		let (reply_tx, reply_rx) = mpsc::channel();
+		let sent = cb_sink.send(Box::new(move |s: &mut Cursive| {
+			let _ = reply_tx.send(handle_command(s, &profile, &command));
+		}));
+		// the UI is gone, so the command line can go to the file itself
+		if sent.is_err() {
+			return Reply::OtherProfile;
+		}
+		// the command is still queued and may yet be applied, so never fall back to the file here
+		reply_rx.recv_timeout(Duration::from_secs(5))
+			.unwrap_or_else(|_| Reply::Failed("the running todolist is busy".to_string()))
+	});
+	if let Err(e) = &listening {
+		log::warn!("Not listening for commands: {}", e);
+	}
+
+	make_menubar(siv.menubar());
+	siv.set_autohide_menu(false);
+
+	// so saving doesn't wait for git, the commits are waited for on exit instead
+	if config.git.enabled {
+		commit_in_background(&config.git);
+	}
 
 	siv.run();
 
-	log::info!("Successfully exited.");
+	if todolist.borrow().is_dirty() {
+		let mut todolist = todolist.borrow_mut();
+		let mut res = todolist.save_to_file();
+		// nobody is left to ask, so our side wins
+		if let Err(SaveError::Conflicts(n)) = res {
+			log::warn!("Keeping our side of {} conflict(s) on exit", n);
+			for i in 0..n {
+				let _ = todolist.resolve_conflict(i, Resolution::Ours);
+			}
+			res = todolist.finish_merge();
+		}
+		if let Err(e) = res {
+			log::error!("Failed to save list on exit: {}", e);
+		}
+	}
+
+	if listening.is_ok() {
+		let _ = fs::remove_file(&socketpath);
+	}
+
+	finish_commits();
+
+	log::info!("Successfully exited.");
+
+	log::logger().flush();
+}
+
+// saves once the list has been left alone for a bit after a change
+fn autosave(s: &mut Cursive) {
+	let autosave = &config().autosave;
+	if autosave.enabled && !SYNCING.load(Ordering::Relaxed) {
+		let res = {
+			let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+			// conflicts from an earlier save are still being resolved
+			let waiting = !todolist.get_conflicts().is_empty();
+			if !waiting && todolist.dirty_for().is_some_and(|dirty_for| dirty_for >= Duration::from_secs(autosave.delay_secs)) {
+				Some(todolist.save_to_file())
+			}
+			else {
+				None
+			}
+		};
+		if let Some(res) = res {
+			if let Err(SaveError::Io(e)) = after_save(s, res) {
+				log::error!("Autosave failed: {}", e);
+			}
+		}
+	}
+	update_dirty_indicator(s);
+}
+
+// the server can take a while, so a copy of the list is synced on another thread
+// the list stays untouched in the meantime and the copy takes its place once it's done
+fn caldav_sync(s: &mut Cursive) {
+	if !NETWORK.load(Ordering::Relaxed) {
+		s.add_layer(Dialog::info("The sandbox doesn't allow network access, enable it with network = true under [sandbox] in the config file")
+			.title("Sync"));
+		return;
+	}
+	if SYNCING.swap(true, Ordering::Relaxed) {
+		return;
+	}
+
+	let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow().clone();
+	s.add_layer(Dialog::text("Syncing with the CalDAV server...")
+		.title("Sync")
+		.with_name("syncing"));
+
+	let cb_sink = s.cb_sink().clone();
+	thread::spawn(move || {
+		let res = caldav::sync(&mut todolist, &config().caldav);
+		let _ = cb_sink.send(Box::new(move |s: &mut Cursive| {
+			*s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut() = todolist;
+			SYNCING.store(false, Ordering::Relaxed);
+			if let Some(layer) = s.screen_mut().find_layer_from_name("syncing") {
+				s.screen_mut().remove_layer(layer);
+			}
+
+			refresh_views(s);
+			update_dirty_indicator(s);
+			let text = match res {
+				// what was pulled is saved right away, the sync only counts once it's in the list file
+				Ok(report) => {
+					let res = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut().save_to_file();
+					let saved = match after_save(s, res) {
+						Ok(()) => {
+							let list_path = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow().get_list_path().to_path_buf();
+							report.save_mapping(&list_path)
+						},
+						Err(e) => Err(format!("Failed to save list: {}", e)),
+					};
+					match saved {
+						Ok(()) => report.to_string(),
+						Err(e) => format!("{}\n{}, the next sync starts from the one before", report, e),
+					}
+				},
+				Err(e) => format!("CalDAV sync failed: {}", e),
+			};
+			s.add_layer(Dialog::info(text)
+				.title("Sync"));
+		}));
+	});
+}
+
+// a change from the command line, only taken if it's for the profile that's open
+fn handle_command(s: &mut Cursive, profile: &str, command: &Command) -> Reply {
+	if profile != current_profile() {
+		return Reply::OtherProfile;
+	}
+	if SYNCING.load(Ordering::Relaxed) {
+		return Reply::Failed("the running todolist is busy syncing with CalDAV".to_string());
+	}
+	let res = {
+		let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+		command.apply(&mut todolist)
+	};
+	refresh_views(s);
+	update_dirty_indicator(s);
+	match res {
+		Ok(message) => Reply::Done(message),
+		Err(e) => Reply::Failed(e),
+	}
+}
+
+// called when the list file was changed, possibly by us
+// while syncing the next save merges it in instead
+fn reload_list(s: &mut Cursive) {
+	if SYNCING.load(Ordering::Relaxed) {
+		return;
+	}
+	let res = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut().reload();
+	match res {
+		Ok(true) => {
+			log::info!("Reloaded list after it changed on disk");
+			refresh_views(s);
+		},
+		Ok(false) => (),
+		Err(e) => log::warn!("Couldn't reload list: {}", e),
+	}
+}
+
+// deals with what saving turned up: merged changes get shown, conflicts get asked about
+fn after_save(s: &mut Cursive, res: Result<bool, SaveError>) -> Result<(), SaveError> {
+	update_dirty_indicator(s);
+	match res {
+		Ok(merged) => {
+			if merged {
+				refresh_views(s);
+			}
+			Ok(())
+		},
+		Err(SaveError::Conflicts(n)) => {
+			resolve_conflicts(s, 0);
+			Err(SaveError::Conflicts(n))
+		},
+		Err(e) => Err(e),
+	}
+}
+
+// asks about each conflict in turn, then saves the result
+fn resolve_conflicts(s: &mut Cursive, index: usize) {
+	let conflicts = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow().get_conflicts();
+	let Some(conflict) = conflicts.get(index) else {
+		let res = {
+			let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+			todolist.finish_merge()
+		};
+		if let Err(e) = after_save(s, res) {
+			s.add_layer(Dialog::info(format!("Failed to save list: {}", e)));
+		}
+		return;
+	};
+
+	let pick = move |resolution: Resolution| {
+		move |s: &mut Cursive| {
+			let _ = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut().resolve_conflict(index, resolution);
+			s.pop_layer();
+			resolve_conflicts(s, index + 1);
+		}
+	};
+
+	s.add_layer(Dialog::around(TextView::new(format!("The list file was changed somewhere else while open here.\n\n{}", conflict.describe())))
+		.title(format!("Conflict {}/{}", index + 1, conflicts.len()))
+		.button("Keep mine", pick(Resolution::Ours))
+		.button("Keep theirs", pick(Resolution::Theirs))
+		// nothing gets saved, the next save merges again and asks all over
+		.button("Cancel", |s| {
+			s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut().abort_merge();
+			s.pop_layer();
+			update_dirty_indicator(s);
+		}));
+}
+
+fn update_dirty_indicator(s: &mut Cursive) {
+	let dirty = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow().is_dirty();
+	s.call_on_name("dirty", |view: &mut TextView| {
+		view.set_content(if dirty { "* unsaved changes" } else { "" });
+	});
+}
+
+fn main_title() -> String {
+	let profile = current_profile();
+	if profile == DEFAULT_PROFILE {
+		"TodoList".to_string()
+	}
+	else {
+		format!("TodoList ({})", profile)
+	}
+}
+
+fn save(s: &mut Cursive) {
+	let res = {
+		let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+		todolist.save_to_file()
+	};
+	let text = match after_save(s, res) {
+		Ok(()) => "Saved list to file successfully!".to_string(),
+		Err(SaveError::Conflicts(_)) => return,
+		Err(e) => format!("Failed to save list: {}", e),
+	};
+	s.add_layer(Dialog::around(TextView::new(text))
+		.button("OK", |s| {
+			s.pop_layer();
+		}));
+}
+
+fn key_event(key: Keystroke) -> Event {
+	match key {
+		Keystroke::Char(c) => Event::Char(c),
+		Keystroke::Ctrl(c) => Event::CtrlChar(c),
+		Keystroke::Alt(c) => Event::AltChar(c),
+		Keystroke::Function(n) => Event::Key(Key::from_f(n)),
+		Keystroke::Named(named) => Event::Key(match named {
+			NamedKey::Enter => Key::Enter,
+			NamedKey::Tab => Key::Tab,
+			NamedKey::Backspace => Key::Backspace,
+			NamedKey::Esc => Key::Esc,
+			NamedKey::Left => Key::Left,
+			NamedKey::Right => Key::Right,
+			NamedKey::Up => Key::Up,
+			NamedKey::Down => Key::Down,
+			NamedKey::Insert => Key::Ins,
+			NamedKey::Del => Key::Del,
+			NamedKey::Home => Key::Home,
+			NamedKey::End => Key::End,
+			NamedKey::PageUp => Key::PageUp,
+			NamedKey::PageDown => Key::PageDown,
+		}),
+	}
+}
+
+// the class highlighted in the classes panel
+fn selected_class(s: &mut Cursive) -> Option<Arc<String>> {
+	s.call_on_name("select", |view: &mut SelectView<String>| view.selection())
+		.flatten()
+		.map(|name| Arc::new((*name).clone()))
+}
+
+// what a key from [keys] does, classname being the class it's for if there is one
+fn run_action(s: &mut Cursive, action: Action, classname: Option<Arc<String>>) {
+	match (action, classname) {
+		(Action::Save, _) => save(s),
+		(Action::Quit, _) => s.quit(),
+		(Action::Help, _) => show_help(s),
+		(Action::Undo, _) => undo(s),
+		(Action::Redo, _) => redo(s),
+		(Action::Search, _) => search(s),
+		(Action::Palette, _) => command_palette(s),
+		(Action::Menu, _) => s.select_menubar(),
+		// with no classes, the first thing to add is one
+		(Action::Add, None) => add_classname(s),
+		(Action::Add, Some(classname)) => add_assignment(s, classname),
+		(Action::Edit, Some(classname)) => edit_assignment(s, classname),
+		(Action::Delete, Some(classname)) => delete_assignment(s, classname),
+		(Action::Toggle, Some(classname)) => toggle_assignment(s, classname),
+		(Action::Edit | Action::Delete | Action::Toggle, None) => {
+			s.add_layer(Dialog::info("There are no classes yet"));
+		},
+	}
+}
+
+// for the class selected in the classes panel
+fn class_action(s: &mut Cursive, action: Action) {
+	let classname = selected_class(s);
+	run_action(s, action, classname);
+}
+
+// everything the menubar and the command palette offer, as menu, label and what it does
+fn commands() -> Vec<(&'static str, &'static str, fn(&mut Cursive))> {
+	let mut commands = vec![];
+	let mut add = |menu: &'static str, label: &'static str, action: fn(&mut Cursive)| {
+		commands.push((menu, label, action));
+	};
+	add("File", "Save", save);
+	add("File", "Import", import_list);
+	add("File", "Export", export_list);
+	add("File", "Switch profile", select_profile);
+	add("File", "Quit", |s| s.quit());
+
+	add("List", "Add class", add_classname);
+	add("List", "Add assignment", |s| class_action(s, Action::Add));
+	add("List", "Edit assignment", |s| class_action(s, Action::Edit));
+	add("List", "Delete assignment", |s| class_action(s, Action::Delete));
+	add("List", "Check off assignment", |s| class_action(s, Action::Toggle));
+	add("List", "Undo", undo);
+	add("List", "Redo", redo);
+	if config().caldav.is_configured() {
+		add("List", "Sync with CalDAV", caldav_sync);
+	}
+
+	add("View", "Week", |s| show_overview(s, false));
+	add("View", "Month", |s| show_overview(s, true));
+	add("View", "Go to date", jump_to_date);
+	add("View", "Range of days", select_range);
+	add("View", "Completed assignments", cycle_completed_display);
+	add("View", "Search", search);
+	add("View", "Smart lists", pick_smart_list);
+	add("View", "New smart list", new_smart_list);
+
+	add("Help", "Keys", show_help);
+	add("Help", "About", |s| {
+		s.add_layer(Dialog::info(format!("todolist {}\n\nReleased under the GNU General Public License, version 2 or later.",
+			env!("CARGO_PKG_VERSION"))));
+	});
+	commands
+}
+
+fn make_menubar(menubar: &mut Menubar) {
+	let mut trees: Vec<(&str, menu::Tree)> = vec![];
+	for (menu_name, label, action) in commands() {
+		match trees.last_mut() {
+			Some((name, tree)) if *name == menu_name => {
+				tree.add_leaf(label, move |s| run_command(s, action));
+			},
+			_ => trees.push((menu_name, menu::Tree::new().leaf(label, move |s| run_command(s, action)))),
+		}
+	}
+	for (name, tree) in trees {
+		menubar.add_subtree(name, tree);
+	}
+}
+
+// nothing can change the list while a sync has a copy of it
+fn run_command(s: &mut Cursive, action: fn(&mut Cursive)) {
+	if !SYNCING.load(Ordering::Relaxed) {
+		action(s);
+	}
+}
+
+// every command from the menus, narrowed down with a fuzzy search as it's typed
+// enter in the search box runs the best match
+fn command_palette(s: &mut Cursive) {
+	fn update(s: &mut Cursive, query: &str) {
+		let mut matches = commands().into_iter()
+			.filter_map(|(menu, label, action)| {
+				let name = format!("{}: {}", menu, label);
+				fuzzy_score(query, &name).map(|score| (score, name, action))
+			})
+			.collect::<Vec<(i64, String, fn(&mut Cursive))>>();
+		// the sort is stable, so they stay in menu order otherwise
+		matches.sort_by(|(a, ..), (b, ..)| b.cmp(a));
+		s.call_on_name("palette", |view: &mut SelectView<fn(&mut Cursive)>| {
+			view.clear();
+			for (_, name, action) in matches {
+				view.add_item(name, action);
+			}
+		});
+	}
+
+	fn run(s: &mut Cursive, action: fn(&mut Cursive)) {
+		s.pop_layer();
+		action(s);
+	}
+
+	let results = SelectView::<fn(&mut Cursive)>::new()
+		.on_submit(|s, action: &fn(&mut Cursive)| run(s, *action))
+		.with_name("palette");
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(EditView::new()
+				.on_edit(|s, query, _| update(s, query))
+				.on_submit(|s, _| {
+					let best = s.call_on_name("palette", |view: &mut SelectView<fn(&mut Cursive)>| {
+						view.get_item(0).map(|(_, action)| *action)
+					}).flatten();
+					if let Some(action) = best {
+						run(s, action);
+					}
+				})
+				.fixed_width(40))
+			.child(DummyView)
+			.child(ScrollView::new(results).max_height(15)))
+		.title("Commands")
+		.button("Close", |s| {
+			s.pop_layer();
+		}));
+	update(s, "");
+}
+
+// reads the tasks of an iCalendar file into the list
+fn import_list(s: &mut Cursive) {
+	fn ok(s: &mut Cursive, path: &str) {
+		let res = fs::read_to_string(expand_home(&PathBuf::from(path)))
+			.map_err(|e| format!("Couldn't read {}: {}", path, e))
+			.and_then(|ics| {
+				let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+				ics::import_ics(&mut todolist, &ics)
+			});
+		s.pop_layer();
+		let text = match res {
+			Ok(report) => report.to_string(),
+			Err(e) => format!("Couldn't import {}: {}", path, e),
+		};
+		refresh_views(s);
+		s.add_layer(Dialog::info(text));
+	}
+
+	let path = default_ics_path(s);
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(TextView::new("Import tasks from the iCalendar file"))
+			.child(EditView::new()
+				.content(path)
+				.on_submit(ok)
+				.with_name("import_path")
+				.fixed_width(40)))
+		.title("Import")
+		.button("Import", |s| {
+			let path = s.call_on_name("import_path", |view: &mut EditView| {
+				view.get_content()
+			}).unwrap();
+			ok(s, &path);
+		})
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
+}
+
+// writes the whole list out as an iCalendar file
+fn export_list(s: &mut Cursive) {
+	fn ok(s: &mut Cursive, path: &str) {
+		let ics = {
+			let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
+			ics::export_ics(&todolist)
+		};
+		let text = match fs::write(expand_home(&PathBuf::from(path)), ics) {
+			Ok(()) => format!("Exported the list to {}", path),
+			Err(e) => format!("Couldn't write {}: {}", path, e),
+		};
+		s.pop_layer();
+		s.add_layer(Dialog::info(text));
+	}
+
+	let path = default_ics_path(s);
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(TextView::new("Export the list to the iCalendar file"))
+			.child(EditView::new()
+				.content(path)
+				.on_submit(ok)
+				.with_name("export_path")
+				.fixed_width(40)))
+		.title("Export")
+		.button("Export", |s| {
+			let path = s.call_on_name("export_path", |view: &mut EditView| {
+				view.get_content()
+			}).unwrap();
+			ok(s, &path);
+		})
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
+}
+
+// next to the list, where the sandbox lets us write
+fn default_ics_path(s: &mut Cursive) -> String {
+	let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
+	todolist.get_list_path()
+		.with_extension("ics")
+		.display()
+		.to_string()
+}
+
+// every key, the configurable ones first
+fn show_help(s: &mut Cursive) {
+	let keys = &config().keys;
+	let mut lines = vec![
+		"From the main screen these act on the class selected in the classes panel,".to_string(),
+		"and in a class dialog on that class.".to_string(),
+		String::new(),
+	];
+	for action in Action::ALL {
+		lines.push(format!("{:<12} {}", keys.key(action), action.description()));
+	}
+	lines.extend([
+		String::new(),
+		"In the week list".to_string(),
+		format!("{:<12} {}", "Space", "check off the selected assignment"),
+		format!("{:<12} {}", "< >", "move a week back or ahead"),
+		format!("{:<12} {}", "t", "go back to this week"),
+		format!("{:<12} {}", "g", "go to a date"),
+		format!("{:<12} {}", "r", "show a range of days"),
+		format!("{:<12} {}", "c", "change how completed assignments show"),
+		String::new(),
+		"In the calendar".to_string(),
+		format!("{:<12} {}", "Arrows", "move between days"),
+		format!("{:<12} {}", "Page Up/Down", "move between months (also < >)"),
+		format!("{:<12} {}", "Home", "go back to today (also t)"),
+		format!("{:<12} {}", "Enter", "list what's due that day"),
+		String::new(),
+		format!("{:<12} {}", "Tab", "move between the panels and buttons"),
+		format!("{:<12} {}", "Esc", "close this help"),
+	]);
+
+	s.add_layer(OnEventView::new(Dialog::around(ScrollView::new(TextView::new(lines.join("\n"))))
+			.title("Keys")
+			.button("Close", |s| {
+				s.pop_layer();
+			}))
+		.on_event(Key::Esc, |s| {
+			s.pop_layer();
+		}));
+}
+
+// rebuilds every view showing the list, for when it changed from under them
+fn refresh_views(s: &mut Cursive) {
+	let todolist_ref = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().clone();
+	s.call_on_name("select", |view: &mut SelectView<String>| {
+		make_class_view(&todolist_ref.borrow(), view);
+	});
+	s.call_on_name("smart_lists", |view: &mut SelectView<String>| {
+		make_smart_list_view(view);
+	});
+	s.call_on_name("weektodo", |view: &mut LinearLayout| {
+		make_todo_list(&todolist_ref.borrow(), view);
+	});
+	s.call_on_name("month", |view: &mut MonthView| {
+		fill_month(&todolist_ref.borrow(), view);
+	});
+	// an open class dialog has the class as its title
+	let classname = s.call_on_name("class_dialog", |dialog: &mut Dialog| dialog.get_title().to_string());
+	if let Some(classname) = classname {
+		if todolist_ref.borrow().get_classes().contains(&classname) {
+			let assign_text = get_assign_text(&todolist_ref.borrow(), classname);
+			s.call_on_name("assigns", |view: &mut TextView| {
+				view.set_content(assign_text);
+			});
+		}
+	}
+	// and an open smart list has its name
+	let smart_list = s.call_on_name("smart_list_dialog", |dialog: &mut Dialog| dialog.get_title().to_string());
+	if let Some(query) = smart_list.and_then(|name| smart_lists().remove(&name)) {
+		s.call_on_name("smart_list", |view: &mut LinearLayout| {
+			fill_smart_list(&todolist_ref.borrow(), &query, view);
+		});
+	}
+}
+
+fn make_class_view(todolist: &TodoList, classes_view: &mut SelectView<String>) {
+	classes_view.clear();
+	classes_view.add_all_str(todolist.get_classes());
+}
+
+fn smart_lists() -> BTreeMap<String, String> {
+	SMART_LISTS.lock().unwrap().clone()
+		.unwrap_or_else(|| config().smart_lists.clone())
+}
+
+fn make_smart_list_view(smart_view: &mut SelectView<String>) {
+	smart_view.clear();
+	smart_view.add_all_str(smart_lists().into_keys());
+}
+
+// None removes it, the config file is changed too so it's still there next time
+fn set_smart_list(s: &mut Cursive, name: &str, query: Option<&str>) {
+	let mut lists = smart_lists();
+	match query {
+		Some(query) => lists.insert(name.to_string(), query.to_string()),
+		None => lists.remove(name),
+	};
+	*SMART_LISTS.lock().unwrap() = Some(lists);
+	if let Err(e) = config().save_setting("smart_lists", name, query) {
+		log::error!("{}", e);
+		s.add_layer(Dialog::info(format!("Couldn't save the smart list to the config file: {}", e)));
+	}
+	s.call_on_name("smart_lists", |view: &mut SelectView<String>| {
+		make_smart_list_view(view);
+	});
+}
+
+// every assignment the query matches, soonest first
+fn fill_smart_list(todolist: &TodoList, query: &str, vert: &mut LinearLayout) {
+	vert.clear();
+	let filter = match Filter::parse(query) {
+		Ok(filter) => filter,
+		Err(e) => {
+			vert.add_child(TextView::new(e));
+			return;
+		},
+	};
+	let assignments = filter.select(todolist);
+	if assignments.is_empty() {
+		vert.add_child(TextView::new("Nothing matches"));
+		return;
+	}
+	add_assign_rows(todolist, assignments, "%b %e %H:%M", vert);
+}
+
+fn show_smart_list(s: &mut Cursive, name: String) {
+	let Some(query) = smart_lists().remove(&name) else {
+		return;
+	};
+	let mut list = LinearLayout::vertical();
+	{
+		let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
+		fill_smart_list(&todolist, &query, &mut list);
+	}
+
+	let rm = {
+		let name = name.clone();
+		move |s: &mut Cursive| {
+			let name = name.clone();
+			s.add_layer(Dialog::around(TextView::new(format!("Are you sure you want to delete smart list \"{}\"", name)))
+				.button("Cancel", |s| {
+					s.pop_layer();
+				})
+				.button("Delete", move |s| {
+					set_smart_list(s, &name, None);
+					s.pop_layer();
+					s.pop_layer();
+				}));
+		}
+	};
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(TextView::new(query))
+			.child(DummyView)
+			.child(ScrollView::new(list.with_name("smart_list"))))
+		.title(name)
+		.button("Delete this smart list", rm)
+		.button("OK", |s| {
+			s.pop_layer();
+		})
+		.with_name("smart_list_dialog"));
+}
+
+fn pick_smart_list(s: &mut Cursive) {
+	let names = smart_lists().into_keys().collect::<Vec<String>>();
+	if names.is_empty() {
+		s.add_layer(Dialog::info("There are no smart lists yet"));
+		return;
+	}
+	let select = SelectView::<String>::new()
+		.with_all_str(names)
+		.on_submit(|s, name: &str| {
+			s.pop_layer();
+			show_smart_list(s, name.to_string());
+		});
+	s.add_layer(Dialog::around(ScrollView::new(select))
+		.title("Smart lists")
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
+}
+
+// asks for a name and a filter, showing how many assignments it matches as it's typed
+fn new_smart_list(s: &mut Cursive) {
+	fn preview(s: &mut Cursive, query: &str) {
+		let text = match Filter::parse(query) {
+			Ok(filter) => {
+				let count = {
+					let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
+					filter.select(&todolist).len()
+				};
+				match count {
+					1 => "1 assignment matches".to_string(),
+					n => format!("{} assignments match", n),
+				}
+			},
+			Err(e) => e,
+		};
+		s.call_on_name("query_preview", |view: &mut TextView| {
+			view.set_content(text);
+		});
+	}
+
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(TextView::new("Name"))
+			.child(EditView::new()
+				.with_name("smart_name")
+				.fixed_width(20))
+			.child(TextView::new("Filter, like class:physics due<+7d !done tag:exam"))
+			.child(EditView::new()
+				.on_edit(|s, query, _| preview(s, query))
+				.with_name("smart_query")
+				.fixed_width(40))
+			.child(TextView::new("").with_name("query_preview")))
+		.title("New smart list")
+		.button("Save", |s| {
+			let name = s.call_on_name("smart_name", |view: &mut EditView| {
+				view.get_content()
+			}).unwrap();
+			let query = s.call_on_name("smart_query", |view: &mut EditView| {
+				view.get_content()
+			}).unwrap();
+
+			let name = name.trim();
+			if name.is_empty() {
+				s.add_layer(Dialog::info("The smart list needs a name"));
+				return;
+			}
+			if let Err(e) = Filter::parse(&query) {
+				s.add_layer(Dialog::info(e));
+				return;
+			}
+			s.pop_layer();
+			set_smart_list(s, name, Some(&query));
+		})
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
+}
+
+// the first and last day the week view shows, around today unless it was moved
+fn week_range() -> (NaiveDate, NaiveDate) {
+	WEEK_RANGE.lock().unwrap().unwrap_or_else(|| {
+		let now = Local::now().date_naive();
+		let week = &config().week;
+		(now.checked_sub_days(Days::new(week.days_before)).unwrap(),
+			now.checked_add_days(Days::new(week.days_after)).unwrap())
+	})
+}
+
+// None goes back to following today
+fn set_week_range(s: &mut Cursive, range: Option<(NaiveDate, NaiveDate)>) {
+	*WEEK_RANGE.lock().unwrap() = range;
+	if s.find_name::<LinearLayout>("weektodo").is_some() {
+		s.call_on_name("overview", |dialog: &mut Dialog| {
+			dialog.set_title(week_title());
+		});
+	}
+	refresh_views(s);
+}
+
+fn week_title() -> String {
+	match *WEEK_RANGE.lock().unwrap() {
+		None => "TODO This Week".to_string(),
+		Some((begin, end)) => format!("TODO {} - {}", begin.format("%b %e"), end.format("%b %e, %Y")),
+	}
+}
+
+// moves the week view by a week either way
+fn shift_week(s: &mut Cursive, weeks: i64) {
+	let (begin, end) = week_range();
+	let days = Days::new(7 * weeks.unsigned_abs());
+	let range = if weeks < 0 {
+		(begin.checked_sub_days(days), end.checked_sub_days(days))
+	}
+	else {
+		(begin.checked_add_days(days), end.checked_add_days(days))
+	};
+	if let (Some(begin), Some(end)) = range {
+		set_week_range(s, Some((begin, end)));
+	}
+}
+
+// keeps the length of the range, with the date as far in as today usually is
+fn jump_to_date(s: &mut Cursive) {
+	fn ok(s: &mut Cursive) {
+		let date = s.call_on_name("jump_date", |view: &mut EditView| view.get_content()).unwrap();
+		let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") else {
+			s.add_layer(Dialog::info("Please enter a YYYY-MM-DD date"));
+			return;
+		};
+		let (begin, end) = week_range();
+		let length = (end - begin).num_days() as u64;
+		let before = config().week.days_before.min(length);
+		let begin = date.checked_sub_days(Days::new(before)).unwrap_or(date);
+		let end = begin.checked_add_days(Days::new(length)).unwrap_or(begin);
+		s.pop_layer();
+		set_week_range(s, Some((begin, end)));
+	}
 
-	log::logger().flush();
+	s.add_layer(Dialog::around(LinearLayout::horizontal()
+			.child(TextView::new("Date (YYYY-MM-DD): "))
+			.child(EditView::new()
+				.content(Local::now().date_naive().format("%Y-%m-%d").to_string())
+				.on_submit(|s, _| ok(s))
+				.with_name("jump_date")
+				.fixed_width(12)))
+		.title("Go to date")
+		.button("Ok", ok)
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
 }
 
-fn make_class_view(todolist: &TodoList, classes_view: &mut SelectView<String>) {
-	classes_view.clear();
-	classes_view.add_all_str(todolist.get_classes());
+// shows any span of days, like the weeks before finals
+fn select_range(s: &mut Cursive) {
+	fn ok(s: &mut Cursive) {
+		let from = s.call_on_name("range_from", |view: &mut EditView| view.get_content()).unwrap();
+		let to = s.call_on_name("range_to", |view: &mut EditView| view.get_content()).unwrap();
+		let (Ok(from), Ok(to)) = (NaiveDate::parse_from_str(&from, "%Y-%m-%d"), NaiveDate::parse_from_str(&to, "%Y-%m-%d")) else {
+			s.add_layer(Dialog::info("Please enter the dates as YYYY-MM-DD"));
+			return;
+		};
+		if to < from {
+			s.add_layer(Dialog::info("The range has to end after it starts"));
+			return;
+		}
+		s.pop_layer();
+		set_week_range(s, Some((from, to)));
+	}
+
+	let (begin, end) = week_range();
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(LinearLayout::horizontal()
+				.child(TextView::new("From (YYYY-MM-DD): "))
+				.child(EditView::new()
+					.content(begin.format("%Y-%m-%d").to_string())
+					.with_name("range_from")
+					.fixed_width(12)))
+			.child(LinearLayout::horizontal()
+				.child(TextView::new("To (YYYY-MM-DD):   "))
+				.child(EditView::new()
+					.content(end.format("%Y-%m-%d").to_string())
+					.on_submit(|s, _| ok(s))
+					.with_name("range_to")
+					.fixed_width(12))))
+		.title("Show days")
+		.button("Ok", ok)
+		.button("This week", |s| {
+			s.pop_layer();
+			set_week_range(s, None);
+		})
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
 }
 
 fn make_todo_list(todolist: &TodoList, vert: &mut LinearLayout) {
 	let now = Local::now().date_naive();
+	let time_format = config().display.time_format.as_str();
+	// these get a section of their own, however long ago they were due
+	let mut overdue = by_due_date(todolist.get_overdue_assignments(Local::now()));
+	overdue.retain(|(class, assign)| matches_search(class, assign));
 	let assignments_by_date = {
-		let all_week_assignments = {
-			let begin = now.checked_sub_days(Days::new(3)).unwrap();
-			let end = now.checked_add_days(Days::new(24)).unwrap();
-			todolist.get_timespan_assignments(begin, end)
-		};
-
-		let mut date_assign = HashMap::new();
-		for i in -3i64..=10 {
-			let date = if i > 0 {
-				now.checked_add_days(Days::new(i as u64)).unwrap()
-			}
-			else if i < 0 {
-				now.checked_sub_days(Days::new(-i as u64)).unwrap()
-			}
-			else {
-				now
-			};
-
-			let mut date_assigns = vec![];
-			for (class, assignments) in &all_week_assignments {
-				for assign in assignments {
-					if assign.due_date.date_naive() == date {
-						date_assigns.push((class.clone(), assign.clone()));
-					}
+		let (begin, end) = week_range();
+		let mut date_assign: HashMap<NaiveDate, Vec<(String, Assignment)>> = HashMap::new();
+		for (class, assignments) in todolist.get_timespan_assignments(begin, end) {
+			for assign in assignments {
+				if overdue.contains(&(class.clone(), assign.clone())) || !matches_search(&class, &assign) {
+					continue;
+				}
+				if let Some(due_date) = assign.due_date {
+					date_assign.entry(due_date.date_naive())
+						.or_default()
+						.push((class.clone(), assign));
 				}
-			}
-
-			if !date_assigns.is_empty() {
-				date_assign.insert(date.clone(), date_assigns);
 			}
 		}
 
 		date_assign
 	};
+	let mut undated = by_due_date(todolist.get_undated_assignments());
+	undated.retain(|(class, assign)| matches_search(class, assign));
 
 	vert.clear();
+	let search = SEARCH.lock().unwrap().clone();
+	if !search.is_empty() {
+		vert.add_child(TextView::new(format!("Only showing matches for \"{}\" (/ to change)", search)));
+		vert.add_child(DummyView);
+	}
+	if !overdue.is_empty() {
+		vert.add_child(TextView::new("Overdue"));
+		add_assign_rows(todolist, overdue, "%b %e", vert);
+		vert.add_child(DummyView);
+	}
+	if assignments_by_date.is_empty() {
+		vert.add_child(TextView::new("Nothing due."));
+		vert.add_child(DummyView);
+	}
 	let mut dates = assignments_by_date.keys().collect::<Vec<&NaiveDate>>();
 	dates.sort();
 	for date in dates {
@@ -231,45 +1555,391 @@ fn make_todo_list(todolist: &TodoList, vert: &mut LinearLayout) {
 				""
 			}
 		};
-		vert.add_child(TextView::new(format!("{}{}", date.format("Due %a, %b %e").to_string(), &notice)));
+		let heading = format!("{}{}", date.format("Due %a, %b %e").to_string(), &notice);
+		add_section(todolist, heading, Some(*date), assignments, time_format, vert);
+	}
 
-		let time_format_str = "%l:%M %p";
+	add_section(todolist, "No due date".to_string(), None, undated, time_format, vert);
+}
 
-		let classname_len = 8;
-		let max_assign_name_len = 32;
-		let banner = "─".repeat(4) + "┬" + &"─".repeat(time_format_str.len() + 2) + "┬" + &"─".repeat(classname_len + 2) + "┬" + &"─".repeat(max_assign_name_len + 1);
-		vert.add_child(TextView::new(banner).no_wrap());
-		for (classname, assign) in assignments {
-			let due_date = assign.due_date.format(time_format_str).to_string();
-			let uid = {
-				let mut h = DefaultHasher::new();
-				assign.hash(&mut h);
-				h.finish()
+// a heading and its assignments, with the completed ones shown however the user picked
+// section is the day, or None for the assignments without a due date
+fn add_section(todolist: &TodoList, heading: String, section: Option<NaiveDate>, assignments: Vec<(String, Assignment)>, time_format: &str, vert: &mut LinearLayout) {
+	let display = completed_display();
+	let expanded = EXPANDED_DAYS.lock().unwrap().contains(&section);
+	let done = assignments.iter()
+		.filter(|(_, assign)| is_completed(todolist, assign))
+		.count();
+	let hide_done = display == CompletedDisplay::Hide || (display == CompletedDisplay::Collapse && !expanded);
+	let assignments = if hide_done {
+		assignments.into_iter()
+			.filter(|(_, assign)| !is_completed(todolist, assign))
+			.collect()
+	}
+	else {
+		assignments
+	};
+	// a section with nothing left to show isn't worth a heading
+	let collapsed = display == CompletedDisplay::Collapse && done > 0;
+	if assignments.is_empty() && !collapsed {
+		return;
+	}
+
+	vert.add_child(TextView::new(heading));
+	if !assignments.is_empty() {
+		add_assign_rows(todolist, assignments, time_format, vert);
+	}
+	if collapsed {
+		let label = if expanded {
+			format!("- hide {} completed", done)
+		}
+		else {
+			format!("+ {} completed", done)
+		};
+		vert.add_child(Button::new_raw(label, move |s| toggle_expanded(s, section)));
+	}
+	vert.add_child(DummyView);
+}
+
+fn is_completed(todolist: &TodoList, assign: &Assignment) -> bool {
+	let uid = {
+		let mut h = DefaultHasher::new();
+		assign.hash(&mut h);
+		h.finish()
+	};
+	todolist.get_assignment_completion(uid).unwrap_or(false)
+}
+
+// flattens assignments by class into one list, sorted by due date, then name, then class
+fn by_due_date(assignments: HashMap<String, Vec<Assignment>>) -> Vec<(String, Assignment)> {
+	let mut assignments = assignments.into_iter()
+		.flat_map(|(class, assignments)| assignments.into_iter().map(move |assign| (class.clone(), assign)))
+		.collect::<Vec<(String, Assignment)>>();
+	assignments.sort_by(|(ca, a), (cb, b)| (a, ca).cmp(&(b, cb)));
+	assignments
+}
+
+// a table of assignments with a checkbox for each, in the order given
+// due dates are shown with time_format_str, left blank for assignments without one
+fn add_assign_rows(todolist: &TodoList, assignments: Vec<(String, Assignment)>, time_format_str: &str, vert: &mut LinearLayout) {
+	let display = &config().display;
+	let time_len = Local::now().format(time_format_str).to_string().chars().count();
+
+	let classname_len = display.class_width;
+	let max_assign_name_len = display.name_width;
+	let banner = "─".repeat(4) + "┬" + &"─".repeat(time_len + 2) + "┬" + &"─".repeat(classname_len + 2) + "┬" + &"─".repeat(max_assign_name_len + 1);
+	vert.add_child(TextView::new(banner).no_wrap());
+	for (classname, assign) in assignments {
+		let due_date = match assign.due_date {
+			Some(due_date) => due_date.format(time_format_str).to_string(),
+			None => " ".repeat(time_len),
+		};
+		let uid = {
+			let mut h = DefaultHasher::new();
+			assign.hash(&mut h);
+			h.finish()
+		};
+
+		// this should probably error out, but it does
+		if let Ok(already_completed) = todolist.get_assignment_completion(uid) {
+			let label = assign_label(todolist, uid, &assign);
+			let label = if already_completed && completed_display() == CompletedDisplay::Dim {
+				StyledString::styled(label, Style::merge(&[Effect::Strikethrough.into(), ColorStyle::secondary().into()]))
+			}
+			else {
+				StyledString::plain(label)
 			};
+			let check = Checkbox::new().with_checked(already_completed)
+				.on_change(move |s, checked| {
+					let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+					todolist.set_assignment_completion(uid, checked).unwrap();
+				});
+
+			vert.add_child(LinearLayout::horizontal()
+				.child(check)
+				.child(TextView::new(" │ ").no_wrap())
+				.child(TextView::new(due_date).no_wrap())
+				.child(TextView::new(" │ ").no_wrap())
+				.child(ScrollView::new(TextView::new(classname).no_wrap().min_width(classname_len).max_width(classname_len)))
+				.child(TextView::new(" │ ").no_wrap())
+				.child(ScrollView::new(TextView::new(label).no_wrap().max_width(max_assign_name_len))));
+		}
+	}
+}
+
+// fills in what's due in the month the calendar shows
+fn fill_month(todolist: &TodoList, month_view: &mut MonthView) {
+	let (begin, end) = month_view.range();
+	let mut due: HashMap<NaiveDate, Vec<String>> = HashMap::new();
+	for (class, assignments) in todolist.get_timespan_assignments(begin, end) {
+		for due_date in assignments.iter().filter_map(|assign| assign.due_date) {
+			due.entry(due_date.date_naive())
+				.or_default()
+				.push(class.clone());
+		}
+	}
+	month_view.set_due(due);
+}
+
+fn make_month_view(todolist: &TodoList) -> NamedView<MonthView> {
+	let mut month_view = MonthView::new(Local::now().date_naive())
+		.on_submit(show_day)
+		.on_month_change(|s, _| {
+			let todolist_ref = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().clone();
+			s.call_on_name("month", |view: &mut MonthView| {
+				fill_month(&todolist_ref.borrow(), view);
+			});
+		});
+	fill_month(todolist, &mut month_view);
+	month_view.with_name("month")
+}
+
+// everything due on one day, from the calendar
+fn show_day(s: &mut Cursive, date: NaiveDate) {
+	let todolist_ref = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().clone();
+	let todolist = todolist_ref.borrow();
+	let mut assignments = by_due_date(todolist.get_timespan_assignments(date, date));
+	assignments.retain(|(class, assign)| matches_search(class, assign));
+
+	let mut vert = LinearLayout::vertical();
+	if assignments.is_empty() {
+		vert.add_child(TextView::new("Nothing due."));
+	}
+	else {
+		add_assign_rows(&todolist, assignments, &config().display.time_format, &mut vert);
+	}
+	s.add_layer(Dialog::around(ScrollView::new(vert))
+		.title(date.format("Due %a, %b %e").to_string())
+		.button("Close", |s| {
+			s.pop_layer();
+			// completions could've changed
+			refresh_views(s);
+		}));
+}
+
+// switches the right side between the week list and the calendar
+// the month calendar or the week list, whichever is showing now
+fn show_overview(s: &mut Cursive, month: bool) {
+	if s.find_name::<MonthView>("month").is_some() != month {
+		toggle_overview(s);
+	}
+}
+
+fn toggle_overview(s: &mut Cursive) {
+	let todolist_ref = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().clone();
+	let showing_week = s.find_name::<LinearLayout>("weektodo").is_some();
+	s.call_on_name("overview", |dialog: &mut Dialog| {
+		if showing_week {
+			dialog.set_content(make_month_view(&todolist_ref.borrow()));
+			dialog.set_title("Calendar");
+		}
+		else {
+			dialog.set_content(make_week_view(&todolist_ref.borrow()));
+			dialog.set_title(week_title());
+		}
+	});
+	s.call_on_name("toggle", |button: &mut Button| {
+		button.set_label(if showing_week { "Week" } else { "Calendar" });
+	});
+}
+
+fn make_week_view(todolist: &TodoList) -> OnEventView<LinearLayout> {
+	let mut vert = LinearLayout::vertical().with_name("weektodo");
+	make_todo_list(todolist, &mut (*vert.get_mut()));
+
+	let nav = LinearLayout::horizontal()
+		.child(Button::new("<", |s| shift_week(s, -1)))
+		.child(DummyView)
+		.child(Button::new("Today", |s| set_week_range(s, None)))
+		.child(DummyView)
+		.child(Button::new(">", |s| shift_week(s, 1)))
+		.child(DummyView)
+		.child(Button::new("Go to", jump_to_date))
+		.child(DummyView)
+		.child(Button::new("Range", select_range))
+		.child(DummyView)
+		.child(Button::new(completed_label(), cycle_completed_display).with_name("completed_display"));
+
+	OnEventView::new(LinearLayout::vertical()
+			.child(nav)
+			.child(ScrollView::new(vert)))
+		.on_event('<', |s| shift_week(s, -1))
+		.on_event('>', |s| shift_week(s, 1))
+		.on_event('t', |s| set_week_range(s, None))
+		.on_event('g', jump_to_date)
+		.on_event('r', select_range)
+		.on_event('c', cycle_completed_display)
+}
+
+fn completed_display() -> CompletedDisplay {
+	COMPLETED_DISPLAY.lock().unwrap().unwrap_or(config().display.completed)
+}
+
+fn completed_label() -> String {
+	format!("Completed: {}", completed_display().name())
+}
+
+// goes on to the next way of showing completed assignments, and remembers it in the config file
+fn cycle_completed_display(s: &mut Cursive) {
+	let display = completed_display().next();
+	*COMPLETED_DISPLAY.lock().unwrap() = Some(display);
+	if let Err(e) = config().save_setting("display", "completed", Some(display.name())) {
+		log::error!("{}", e);
+	}
+	s.call_on_name("completed_display", |button: &mut Button| {
+		button.set_label(completed_label());
+	});
+	refresh_views(s);
+}
+
+fn toggle_expanded(s: &mut Cursive, date: Option<NaiveDate>) {
+	{
+		let mut expanded = EXPANDED_DAYS.lock().unwrap();
+		if !expanded.remove(&date) {
+			expanded.insert(date);
+		}
+	}
+	refresh_views(s);
+}
+
+// the assignment's name, marked with the script managing it if there is one
+// and followed by its tags
+fn assign_label(todolist: &TodoList, uid: u64, assign: &Assignment) -> String {
+	let label = match &assign.source {
+		Some(source) if todolist.is_removed_upstream(uid) => format!("{} [{}, removed upstream]", assign.name, source.script),
+		Some(source) => format!("{} [{}]", assign.name, source.script),
+		None => assign.name.clone(),
+	};
+	assign.tags.iter()
+		.fold(label, |label, tag| label + " #" + tag)
+}
 
-			// this should probably error out, but it does
-			if let Ok(already_completed) = todolist.get_assignment_completion(uid) {
-				let check = Checkbox::new().with_checked(already_completed)
-					.on_change(move |s, checked| {
-						let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
-						todolist.set_assignment_completion(uid, checked).unwrap();
-					});
-
-				vert.add_child(LinearLayout::horizontal()
-					.child(check)
-					.child(TextView::new(" │ ").no_wrap())
-					.child(TextView::new(due_date).no_wrap())
-					.child(TextView::new(" │ ").no_wrap())
-					.child(ScrollView::new(TextView::new(classname).no_wrap().min_width(classname_len).max_width(classname_len)))
-					.child(TextView::new(" │ ").no_wrap())
-					.child(ScrollView::new(TextView::new(assign.name).no_wrap().max_width(max_assign_name_len))));
+fn matches_search(classname: &str, assign: &Assignment) -> bool {
+	let search = SEARCH.lock().unwrap();
+	if search.is_empty() {
+		return true;
+	}
+	let texts = [assign.name.as_str(), classname].into_iter()
+		.chain(assign.tags.iter().map(|tag| tag.as_str()))
+		.collect::<Vec<&str>>();
+	fuzzy_matches(&search, &texts)
+}
+
+// narrows everything down as the query is typed, and lists what matched best
+// picking a result opens its class
+fn search(s: &mut Cursive) {
+	fn update(s: &mut Cursive, query: &str) {
+		*SEARCH.lock().unwrap() = query.to_string();
+		refresh_views(s);
+
+		let todolist_ref = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().clone();
+		let todolist = todolist_ref.borrow();
+		let mut results = vec![];
+		for class in todolist.get_classes() {
+			for assign in todolist.get_class_assignments(&class).unwrap() {
+				let score = [fuzzy_score(query, &assign.name), fuzzy_score(query, &class)]
+					.into_iter()
+					.chain(assign.tags.iter().map(|tag| fuzzy_score(query, tag)))
+					.flatten()
+					.max();
+				if let Some(score) = score {
+					results.push((score, class.clone(), assign));
+				}
 			}
 		}
-		vert.add_child(DummyView);
+		results.sort_by(|(a_score, _, a), (b_score, _, b)| b_score.cmp(a_score).then(a.cmp(b)));
+
+		s.call_on_name("search_results", |view: &mut SelectView<String>| {
+			view.clear();
+			if query.is_empty() {
+				return;
+			}
+			for (_, class, assign) in results.iter().take(50) {
+				view.add_item(format!("{:<12} {:<32} {}", class, assign.name, due_text(assign)), class.clone());
+			}
+		});
+	}
+
+	let query = SEARCH.lock().unwrap().clone();
+	let results = SelectView::<String>::new()
+		.on_submit(|s, class: &str| {
+			s.pop_layer();
+			select_class(s, Arc::new(class.to_string()));
+		})
+		.with_name("search_results");
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(EditView::new()
+				.content(query.clone())
+				.on_edit(|s, query, _| update(s, query))
+				.fixed_width(40))
+			.child(DummyView)
+			.child(ScrollView::new(results).max_height(15)))
+		.title("Search")
+		.button("Close", |s| {
+			s.pop_layer();
+		})
+		.button("Clear", |s| {
+			s.pop_layer();
+			SEARCH.lock().unwrap().clear();
+			refresh_views(s);
+		}));
+	update(s, &query);
+}
+
+// picks the due date of the assignment dialog from a calendar instead,
+// starting from what's typed in if that makes sense, and tomorrow at the default time otherwise
+fn pick_due(s: &mut Cursive) {
+	fn set(s: &mut Cursive, value: NaiveDateTime) {
+		s.pop_layer();
+		let content = value.format("%Y-%m-%d %H:%M").to_string();
+		// this runs on_edit, so the preview follows
+		let cb = s.call_on_name("due", |view: &mut EditView| view.set_content(content));
+		if let Some(cb) = cb {
+			cb(s);
+		}
+	}
+
+	let due_str = s.call_on_name("due", |view: &mut EditView| view.get_content()).unwrap();
+	let default_time = config().display.default_due_time();
+	let value = match parse_due(&due_str, Local::now(), default_time) {
+		Ok(Some(due_date)) => due_date.naive_local(),
+		_ => {
+			let tomorrow = Local::now().date_naive().checked_add_days(Days::new(1)).unwrap();
+			tomorrow.and_time(default_time)
+		},
+	};
+
+	s.add_layer(Dialog::around(DatePicker::new(value)
+			.on_submit(set)
+			.with_name("date_picker"))
+		.title("Due")
+		.button("OK", |s| {
+			let value = s.call_on_name("date_picker", |view: &mut DatePicker| view.value()).unwrap();
+			set(s, value);
+		})
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
+}
+
+// what the due field of the assignment dialog would set
+fn due_preview(due_str: &str) -> String {
+	match parse_due(due_str, Local::now(), config().display.default_due_time()) {
+		Ok(Some(due_date)) => due_date.format("Due %a, %B %e %Y, %l:%M %p").to_string(),
+		Ok(None) => "No due date".to_string(),
+		Err(e) => e,
+	}
+}
+
+fn due_text(assign: &Assignment) -> String {
+	match assign.due_date {
+		Some(due_date) => due_date.format("Due %a, %B %e, %l:%M %p").to_string(),
+		None => "No due date".to_string(),
 	}
 }
 
-// returns a string of all assigments from 3 days ago to infinity
+// returns a string of all assigments from the start of the week view to infinity,
+// and every uncompleted one before that
 // each assignment is seperated by newline
 fn get_assign_text(todolist: &TodoList, classname: String) -> String {
 	let assignments = {
@@ -282,13 +1952,22 @@ fn get_assign_text(todolist: &TodoList, classname: String) -> String {
 	// transform each assignment into a string, then move each string into a megastring to return
 	assignments.iter()
 		.filter_map(|assign| {
-			let offset = (assign.due_date.date_naive() - now).num_seconds();
+			let uid = {
+				let mut h = DefaultHasher::new();
+				assign.hash(&mut h);
+				h.finish()
+			};
+			let recent = assign.due_date.is_none_or(|due_date| {
+				let offset = (due_date.date_naive() - now).num_seconds();
+				offset >= -(60 * 60 * 24 * config().week.days_before as i64)
+			});
+			let completed = todolist.get_assignment_completion(uid).unwrap_or(false);
 
-			// only write if it's not more than 3 days earlier than today
-			if offset >= -(60 * 60 * 24 * 3) {
+			// only write if it's not earlier than the week view goes, unless it's still to be done
+			if (recent || !completed) && matches_search(&classname, assign) {
 				Some(format!("{:<32} {}\n",
-						assign.name,
-						assign.due_date.format("Due %a, %B %e, %l:%M %p"))
+						assign_label(todolist, uid, assign),
+						due_text(assign))
 					.to_string())
 			}
 			else {
@@ -338,12 +2017,34 @@ fn select_class(s: &mut Cursive, name: Arc<String>) {
 				}));
 		}
 	};
-	s.add_layer(Dialog::around(ScrollView::new(text_view))
+	let edit = {
+		let name = name.clone();
+		move |s: &mut Cursive| {
+			edit_assignment(s, name.clone());
+		}
+	};
+	let dialog = Dialog::around(ScrollView::new(text_view))
+		.title((*name).clone())
 		.button("Add new assignment", add)
+		.button("Edit an assignment", edit)
 		.button("Delete this class", rm)
 		.button("OK", |s| {
 			s.pop_layer();
-		}));
+		})
+		.with_name("class_dialog");
+
+	// the keys act on this class instead of the one selected behind it
+	let mut dialog = OnEventView::new(dialog);
+	for action in Action::ALL {
+		if action.anywhere() {
+			continue;
+		}
+		let name = name.clone();
+		dialog.set_on_event(key_event(config().keys.key(action)), move |s| {
+			run_action(s, action, Some(name.clone()));
+		});
+	}
+	s.add_layer(dialog);
 }
 
 fn add_classname(s: &mut Cursive) {
@@ -381,76 +2082,177 @@ fn add_classname(s: &mut Cursive) {
 }
 
 fn add_assignment(s: &mut Cursive, classname: Arc<String>) {
-	let tomorrow = Local::now().date_naive().checked_add_days(Days::new(1)).unwrap();
+	assignment_dialog(s, classname, None);
+}
+
+// lets the user pick one of the class' assignments to edit
+fn edit_assignment(s: &mut Cursive, classname: Arc<String>) {
+	let picked = classname.clone();
+	pick_assignment(s, &classname, "Edit which assignment?", move |s, uid| {
+		assignment_dialog(s, picked.clone(), Some(uid));
+	});
+}
+
+fn delete_assignment(s: &mut Cursive, classname: Arc<String>) {
+	pick_assignment(s, &classname, "Delete which assignment?", |s, uid| {
+		let name = {
+			let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
+			todolist.get_assignment(uid).map(|assign| assign.name).unwrap_or_default()
+		};
+		s.add_layer(Dialog::around(TextView::new(format!("Are you sure you want to delete assignment \"{}\"", name)))
+			.button("Cancel", |s| {
+				s.pop_layer();
+			})
+			.button("Delete", move |s| {
+				let res = {
+					let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+					todolist.delete_assignment(uid)
+				};
+				s.pop_layer();
+				if res.is_err() {
+					s.add_layer(Dialog::info("Failed to delete assignment"));
+					return;
+				}
+				refresh_views(s);
+			}));
+	});
+}
+
+fn toggle_assignment(s: &mut Cursive, classname: Arc<String>) {
+	pick_assignment(s, &classname, "Check off which assignment?", |s, uid| {
+		{
+			let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+			if let Ok(completed) = todolist.get_assignment_completion(uid) {
+				let _ = todolist.set_assignment_completion(uid, !completed);
+			}
+		}
+		refresh_views(s);
+	});
+}
+
+// lists the class' assignments, and closes to hand on the one picked
+fn pick_assignment<F>(s: &mut Cursive, classname: &str, title: &str, picked: F)
+	where F: Fn(&mut Cursive, u64) + Send + Sync + 'static
+{
+	let mut select = SelectView::<u64>::new();
+	{
+		let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
+		let mut assignments = todolist.get_class_assignments(&classname.to_string()).unwrap_or_default();
+		assignments.sort();
+		for assign in assignments {
+			let uid = {
+				let mut h = DefaultHasher::new();
+				assign.hash(&mut h);
+				h.finish()
+			};
+			let completed = todolist.get_assignment_completion(uid).unwrap_or(false);
+			select.add_item(format!("[{}] {:<32} {}", if completed { "x" } else { " " }, assign.name, due_text(&assign)), uid);
+		}
+	}
+	let select = select.on_submit(move |s, uid: &u64| {
+		s.pop_layer();
+		picked(s, *uid);
+	});
+
+	s.add_layer(Dialog::around(ScrollView::new(select))
+		.title(title)
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
+}
+
+// the dialog for adding a new assignment, or editing the one behind editing
+fn assignment_dialog(s: &mut Cursive, classname: Arc<String>, editing: Option<u64>) {
+	let existing = editing.and_then(|uid| {
+		let todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow();
+		todolist.get_assignment(uid).ok()
+	});
+	let tags_str = existing.as_ref()
+		.map(|assign| assign.tags.join(" "))
+		.unwrap_or_default();
+	// new ones are due tomorrow at the default time unless they're changed
+	let (name_str, due_str) = match &existing {
+		Some(assign) => (assign.name.clone(),
+			assign.due_date.map(|due_date| due_date.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default()),
+		None => (String::new(), "tomorrow".to_string()),
+	};
+	let source = existing.and_then(|assign| assign.source);
+
 	let name = EditView::new()
+		.content(name_str)
 		.with_name("new_name")
 		.fixed_width(20);
-	let date = EditView::new()
-		.content(tomorrow.format("%Y-%m-%d").to_string())
-		.with_name("date")
-		.fixed_width(11);
-	let time = EditView::new()
-		.content("08:00")
-		.with_name("time")
-		.fixed_width(6);
+	let preview = TextView::new(due_preview(&due_str))
+		.with_name("due_preview");
+	let due = EditView::new()
+		.content(due_str)
+		.on_edit(|s, due_str, _| {
+			s.call_on_name("due_preview", |view: &mut TextView| {
+				view.set_content(due_preview(due_str));
+			});
+		})
+		.with_name("due")
+		.fixed_width(30);
+	let tags = EditView::new()
+		.content(tags_str)
+		.with_name("tags")
+		.fixed_width(20);
 	s.add_layer(Dialog::around(LinearLayout::vertical()
 			.child(name)
-			.child(date)
-			.child(time))
-		.title("Enter a new assignment")
+			.child(TextView::new("Due, like \"tomorrow 5pm\", \"next fri\" or \"dec 3\""))
+			.child(LinearLayout::horizontal()
+				.child(due)
+				.child(DummyView)
+				.child(Button::new("Pick", pick_due)))
+			.child(preview)
+			.child(TextView::new("Tags"))
+			.child(tags))
+		.title(if editing.is_some() { "Edit assignment" } else { "Enter a new assignment" })
 		.button("OK", move |s| {
 			let name = s.call_on_name("new_name", |view: &mut EditView| {
 				view.get_content()
 			}).unwrap();
-			let date_str = s.call_on_name("date", |view: &mut EditView| {
+			let due_str = s.call_on_name("due", |view: &mut EditView| {
 				view.get_content()
 			}).unwrap();
-			let time_str = s.call_on_name("time", |view: &mut EditView| {
+			let tags_str = s.call_on_name("tags", |view: &mut EditView| {
 				view.get_content()
 			}).unwrap();
 
+			// leaving it empty makes it a someday item
+			let due_date = match parse_due(&due_str, Local::now(), config().display.default_due_time()) {
+				Ok(due_date) => due_date,
+				Err(e) => {
+					s.add_layer(Dialog::info(format!("Couldn't read the due date: {}", e)));
+					return;
+				},
+			};
+
 			let todolist_ref = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().clone();
-			let assign_text = {
+			let res = {
 				let mut todolist = todolist_ref.borrow_mut();
-
-				let date = NaiveDate::parse_from_str(&date_str, "%Y-%m-%d").ok();
-				let time = NaiveTime::parse_from_str(&time_str, "%H:%M").ok();
-				let uid_opt = if let (Some(good_date), Some(good_time)) = (date, time) {
-					let due_date = NaiveDateTime::new(good_date, good_time)
-						.and_local_timezone(Local)
-						.unwrap();
-					Some(todolist.create_assignment(classname.to_string(), Assignment {
-							due_date,
-							name: (*name).clone(),
-						}).unwrap())
-				}
-				else {
-					let dialog = Dialog::around(TextView::new("Formating error with date/time")).button("Ok", |s| {
-						s.pop_layer();
-					});
-					s.add_layer(dialog);
-					None
+				let assign = Assignment {
+					due_date,
+					name: (*name).clone(),
+					source: source.clone(),
+					tags: Assignment::parse_tags(&tags_str),
+					id: 0,
 				};
-
-				match uid_opt {
-					Some(_) => {
-						s.pop_layer();
-					}
-					None => {
-						let dialog = Dialog::around(TextView::new("Failed to add new assignment")).button("Ok", |s| {
-							s.pop_layer();
-						});
-						s.add_layer(dialog);
-						s.pop_layer();
-					}
+				match editing {
+					Some(uid) => todolist.edit_assignment(uid, assign),
+					None => todolist.create_assignment(classname.to_string(), assign),
 				}
-
-				get_assign_text(&todolist, (*classname).clone())
 			};
 
-			s.call_on_name("weektodo", move |list: &mut LinearLayout| {
-				let todolist = todolist_ref.borrow();
-				make_todo_list(&todolist, list);
+			if res.is_err() {
+				s.add_layer(Dialog::info(if editing.is_some() { "Failed to edit assignment" } else { "Failed to add new assignment" }));
+				return;
+			}
+			s.pop_layer();
+
+			let assign_text = get_assign_text(&todolist_ref.borrow(), (*classname).clone());
+			s.call_on_name("weektodo", |list: &mut LinearLayout| {
+				make_todo_list(&todolist_ref.borrow(), list);
 			});
 			s.call_on_name("assigns", move |list: &mut TextView| {
 				list.set_content(assign_text);
@@ -460,3 +2262,105 @@ fn add_assignment(s: &mut Cursive, classname: Arc<String>) {
 			s.pop_layer();
 		}));
 }
+
+fn undo(s: &mut Cursive) {
+	let res = {
+		let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+		todolist.undo()
+	};
+	match res {
+		Ok(change) => log::info!("Undid {}", change.describe()),
+		Err(()) => {
+			s.add_layer(Dialog::info("Nothing to undo"));
+			return;
+		},
+	}
+	refresh_views(s);
+}
+
+fn redo(s: &mut Cursive) {
+	let res = {
+		let mut todolist = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().borrow_mut();
+		todolist.redo()
+	};
+	match res {
+		Ok(change) => log::info!("Redid {}", change.describe()),
+		Err(()) => {
+			s.add_layer(Dialog::info("Nothing to redo"));
+			return;
+		},
+	}
+	refresh_views(s);
+}
+
+// lets the user switch to another profile, or make a new one
+fn select_profile(s: &mut Cursive) {
+	fn switch(s: &mut Cursive, name: &str) {
+		if let Err(e) = create_profile(name) {
+			s.add_layer(Dialog::info(e));
+			return;
+		}
+
+		let todolist_ref = s.user_data::<Arc<RefCell<TodoList>>>().unwrap().clone();
+		match TodoList::new(profile_list_path(name), profile_scripts_path(name)) {
+			Ok(new_todolist) => {
+				log::info!("Switching to profile {}", name);
+				let res = todolist_ref.borrow_mut().save_to_file();
+				match after_save(s, res) {
+					Ok(()) => (),
+					// the conflicts have to be sorted out before leaving
+					Err(SaveError::Conflicts(_)) => return,
+					Err(e) => {
+						s.add_layer(Dialog::info(format!("Couldn't save profile \"{}\": {}", current_profile(), e)));
+						return;
+					},
+				}
+				*todolist_ref.borrow_mut() = new_todolist;
+				set_current_profile(name);
+				if let Err(e) = watch_list(&profile_list_path(name)) {
+					log::warn!("Not watching the list file: {}", e);
+				}
+			},
+			Err(e) => {
+				s.add_layer(Dialog::info(format!("Couldn't open profile \"{}\": {}", name, e)));
+				return;
+			},
+		}
+
+		s.pop_layer();
+		refresh_views(s);
+		s.call_on_name("main", |view: &mut Dialog| {
+			view.set_title(main_title());
+		});
+	}
+
+	let profiles = {
+		let mut profiles = SelectView::<String>::new();
+		let current = current_profile();
+		for profile in get_profiles() {
+			let label = if profile == current {
+				format!("{} (current)", profile)
+			}
+			else {
+				profile.clone()
+			};
+			profiles.add_item(label, profile);
+		}
+		profiles.on_submit(|s, name: &String| {
+			switch(s, name);
+		})
+	};
+
+	s.add_layer(Dialog::around(LinearLayout::vertical()
+			.child(ScrollView::new(profiles))
+			.child(DummyView)
+			.child(TextView::new("New profile:"))
+			.child(EditView::new()
+				.on_submit(switch)
+				.with_name("new_profile")
+				.fixed_width(20)))
+		.title("Profiles")
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}));
+}
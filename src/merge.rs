@@ -0,0 +1,304 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::assignment::Assignment;
+
+// the saved contents of a list: class name to assignment id to (assignment, completed)
+// ids stay the same when an assignment is edited, so both sides agree on which one is which
+pub type Snapshot = BTreeMap<String, BTreeMap<u64, (Assignment, bool)>>;
+
+// an assignment both sides changed in different ways
+// None means that side deleted it (or never had it)
+#[derive(Clone)]
+pub struct Conflict {
+	pub classname: String,
+	pub id: u64,
+	pub ours: Option<(Assignment, bool)>,
+	pub theirs: Option<(Assignment, bool)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+	Ours,
+	Theirs,
+}
+
+impl Conflict {
+	pub fn describe(&self) -> String {
+		// names and due dates only come into it if those are what differs
+		let edited = match (&self.ours, &self.theirs) {
+			(Some((ours, _)), Some((theirs, _))) => ours != theirs,
+			_ => false,
+		};
+		let side = |entry: &Option<(Assignment, bool)>| -> String {
+			match entry {
+				Some((assignment, completed)) => {
+					let completed = if *completed { "completed" } else { "not completed" };
+					if edited {
+						let due = assignment.due_date
+							.map(|due| format!(", due {}", due.format("%Y-%m-%d %H:%M")))
+							.unwrap_or_default();
+						format!("\"{}\"{}, {}", assignment.name, due, completed)
+					}
+					else {
+						completed.to_string()
+					}
+				},
+				None => "deleted".to_string(),
+			}
+		};
+		let name = self.ours.as_ref()
+			.or(self.theirs.as_ref())
+			.map(|(assignment, _)| assignment.name.as_str())
+			.unwrap_or_default();
+		format!("{} / {}: {} here, {} in the file", self.classname, name, side(&self.ours), side(&self.theirs))
+	}
+
+	pub fn resolved(&self, resolution: Resolution) -> Option<&(Assignment, bool)> {
+		match resolution {
+			Resolution::Ours => self.ours.as_ref(),
+			Resolution::Theirs => self.theirs.as_ref(),
+		}
+	}
+}
+
+// the usual three-way rule: if only one side changed something, take that side
+fn merge_value<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+	if ours == theirs || theirs == base {
+		Some(ours.clone())
+	}
+	else if ours == base {
+		Some(theirs.clone())
+	}
+	else {
+		None
+	}
+}
+
+// one assignment that's on every side, where what it says and whether it's done are merged on their own
+// so an edit on one side and a completion on the other both go through
+fn merge_entry(base: &(Assignment, bool), ours: &(Assignment, bool), theirs: &(Assignment, bool)) -> Option<(Assignment, bool)> {
+	let assignment = merge_value(&base.0, &ours.0, &theirs.0)?;
+	let completed = merge_value(&base.1, &ours.1, &theirs.1)?;
+	Some((assignment, completed))
+}
+
+// merges two descendants of base, assignment by assignment
+// conflicting assignments are left out of the result, to be put back with apply_resolution
+pub fn merge(base: &Snapshot, ours: &Snapshot, theirs: &Snapshot) -> (Snapshot, Vec<Conflict>) {
+	let mut merged = Snapshot::new();
+	let mut conflicts = vec![];
+	let empty = BTreeMap::new();
+
+	let classes: BTreeSet<&String> = base.keys()
+		.chain(ours.keys())
+		.chain(theirs.keys())
+		.collect();
+	for classname in classes {
+		// a class can't conflict with itself, it's either there or it isn't
+		let exists = merge_value(&base.contains_key(classname),
+			&ours.contains_key(classname),
+			&theirs.contains_key(classname)).unwrap();
+
+		let base_class = base.get(classname).unwrap_or(&empty);
+		let ours_class = ours.get(classname).unwrap_or(&empty);
+		let theirs_class = theirs.get(classname).unwrap_or(&empty);
+
+		let mut merged_class = BTreeMap::new();
+		let ids: BTreeSet<&u64> = base_class.keys()
+			.chain(ours_class.keys())
+			.chain(theirs_class.keys())
+			.collect();
+		for id in ids {
+			let base_entry = base_class.get(id).cloned();
+			let ours_entry = ours_class.get(id).cloned();
+			let theirs_entry = theirs_class.get(id).cloned();
+
+			let entry = match (&base_entry, &ours_entry, &theirs_entry) {
+				(Some(base_entry), Some(ours_entry), Some(theirs_entry)) => merge_entry(base_entry, ours_entry, theirs_entry).map(Some),
+				// added or deleted somewhere, which only goes through if the other side left it alone
+				_ => merge_value(&base_entry, &ours_entry, &theirs_entry),
+			};
+			match entry {
+				Some(Some(entry)) => {
+					merged_class.insert(*id, entry);
+				},
+				Some(None) => (),
+				None => conflicts.push(Conflict {
+					classname: classname.clone(),
+					id: *id,
+					ours: ours_entry,
+					theirs: theirs_entry,
+				}),
+			}
+		}
+
+		if exists || !merged_class.is_empty() {
+			merged.insert(classname.clone(), merged_class);
+		}
+	}
+
+	(merged, conflicts)
+}
+
+// puts a conflicting assignment back into a merge result the way the user chose
+pub fn apply_resolution(merged: &mut Snapshot, conflict: &Conflict, resolution: Resolution) {
+	if let Some(entry) = conflict.resolved(resolution) {
+		merged.entry(conflict.classname.clone())
+			.or_default()
+			.insert(conflict.id, entry.clone());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testutil::assignment;
+
+	// "Lab <id>" with that id
+	fn lab(id: u64) -> Assignment {
+		Assignment {
+			id,
+			..assignment(&format!("Lab {}", id), None, "")
+		}
+	}
+
+	// one class, with the assignments as lab() makes them
+	fn snapshot(entries: &[(u64, bool)]) -> Snapshot {
+		let class = entries.iter()
+			.map(|(id, completed)| (*id, (lab(*id), *completed)))
+			.collect();
+		Snapshot::from([("Physics".to_string(), class)])
+	}
+
+	// id to completed, for what ended up in the merge
+	fn completion(merged: &Snapshot) -> BTreeMap<u64, bool> {
+		merged.get("Physics")
+			.into_iter()
+			.flatten()
+			.map(|(id, (_, completed))| (*id, *completed))
+			.collect()
+	}
+
+	// the same as snapshot(), with the assignment behind id renamed
+	fn renamed(entries: &[(u64, bool)], id: u64, name: &str) -> Snapshot {
+		let mut snapshot = snapshot(entries);
+		snapshot.get_mut("Physics").unwrap().get_mut(&id).unwrap().0.name = name.to_string();
+		snapshot
+	}
+
+	#[test]
+	fn ours_only() {
+		let base = snapshot(&[(1, false), (2, false)]);
+		let ours = snapshot(&[(1, true), (3, false)]);
+		let (merged, conflicts) = merge(&base, &ours, &base);
+		assert!(conflicts.is_empty());
+		assert_eq!(completion(&merged), BTreeMap::from([(1, true), (3, false)]));
+	}
+
+	#[test]
+	fn theirs_only() {
+		let base = snapshot(&[(1, false), (2, false)]);
+		let theirs = snapshot(&[(1, true), (3, false)]);
+		let (merged, conflicts) = merge(&base, &base, &theirs);
+		assert!(conflicts.is_empty());
+		assert_eq!(completion(&merged), BTreeMap::from([(1, true), (3, false)]));
+	}
+
+	#[test]
+	fn both_same() {
+		let base = snapshot(&[(1, false), (2, false)]);
+		let both = snapshot(&[(1, true), (3, false)]);
+		let (merged, conflicts) = merge(&base, &both, &both);
+		assert!(conflicts.is_empty());
+		assert_eq!(completion(&merged), BTreeMap::from([(1, true), (3, false)]));
+	}
+
+	#[test]
+	fn different_ids_changed() {
+		let base = snapshot(&[(1, false), (2, false)]);
+		let ours = snapshot(&[(1, true), (2, false)]);
+		let theirs = snapshot(&[(1, false)]);
+		let (merged, conflicts) = merge(&base, &ours, &theirs);
+		assert!(conflicts.is_empty());
+		assert_eq!(completion(&merged), BTreeMap::from([(1, true)]));
+	}
+
+	#[test]
+	fn edited_and_completed() {
+		// renamed here, completed there, which is one assignment and not two
+		let base = snapshot(&[(1, false)]);
+		let ours = renamed(&[(1, false)], 1, "Lab 1 report");
+		let theirs = snapshot(&[(1, true)]);
+		let (merged, conflicts) = merge(&base, &ours, &theirs);
+		assert!(conflicts.is_empty());
+		let (assignment, completed) = merged.get("Physics").unwrap().get(&1).unwrap();
+		assert_eq!((assignment.name.as_str(), *completed), ("Lab 1 report", true));
+		assert_eq!(merged.get("Physics").unwrap().len(), 1);
+	}
+
+	#[test]
+	fn edited_both_ways() {
+		let base = snapshot(&[(1, false)]);
+		let ours = renamed(&[(1, false)], 1, "Lab 1 report");
+		let theirs = renamed(&[(1, false)], 1, "Lab 1 slides");
+		let (mut merged, conflicts) = merge(&base, &ours, &theirs);
+		assert_eq!(conflicts.len(), 1);
+		assert!(completion(&merged).is_empty());
+		assert_eq!(conflicts[0].describe(), "Physics / Lab 1 report: \"Lab 1 report\", not completed here, \"Lab 1 slides\", not completed in the file");
+
+		apply_resolution(&mut merged, &conflicts[0], Resolution::Theirs);
+		assert_eq!(merged.get("Physics").unwrap().get(&1).unwrap().0.name, "Lab 1 slides");
+	}
+
+	#[test]
+	fn conflicting() {
+		let base = snapshot(&[(1, false), (2, true)]);
+		// completed here, deleted there
+		let ours = snapshot(&[(1, true), (2, false)]);
+		// and a completion undone here while it was deleted there
+		let theirs = snapshot(&[]);
+		let (mut merged, conflicts) = merge(&base, &ours, &theirs);
+		assert_eq!(conflicts.len(), 2);
+		assert!(completion(&merged).is_empty());
+
+		let first = conflicts.iter().find(|conflict| conflict.id == 1).unwrap();
+		assert_eq!(first.describe(), "Physics / Lab 1: completed here, deleted in the file");
+		assert!(first.theirs.is_none());
+		let second = conflicts.iter().find(|conflict| conflict.id == 2).unwrap();
+		assert_eq!(second.describe(), "Physics / Lab 2: not completed here, deleted in the file");
+
+		apply_resolution(&mut merged, first, Resolution::Ours);
+		apply_resolution(&mut merged, second, Resolution::Theirs);
+		assert_eq!(completion(&merged), BTreeMap::from([(1, true)]));
+	}
+
+	#[test]
+	fn classes() {
+		let base = snapshot(&[]);
+		let mut ours = base.clone();
+		ours.insert("Chemistry".to_string(), BTreeMap::new());
+		let theirs = Snapshot::new();
+		// the class got added here and deleted there, both go through
+		let (merged, conflicts) = merge(&base, &ours, &theirs);
+		assert!(conflicts.is_empty());
+		assert_eq!(merged.keys().collect::<Vec<&String>>(), ["Chemistry"]);
+	}
+}
@@ -0,0 +1,125 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+// the data and scripts directories of the default profile
+// every other profile lives in a "profiles" directory inside of them
+struct ProfileRoots {
+	data: PathBuf,
+	scripts: PathBuf,
+}
+
+static ROOTS: OnceLock<ProfileRoots> = OnceLock::new();
+static CURRENT: Mutex<String> = Mutex::new(String::new());
+
+pub fn set_profile_roots(data: PathBuf, scripts: PathBuf) {
+	let _ = ROOTS.set(ProfileRoots {
+		data,
+		scripts,
+	});
+}
+
+fn roots() -> &'static ProfileRoots {
+	ROOTS.get().expect("profile roots used before being set")
+}
+
+// profile names end up as directory names, so keep them boring
+pub fn validate_profile_name(name: &str) -> Result<(), String> {
+	if name.is_empty() {
+		Err("Profile names can't be empty".to_string())
+	}
+	else if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+		Err(format!("Profile name \"{}\" can only contain letters, digits, - and _", name))
+	}
+	else {
+		Ok(())
+	}
+}
+
+pub fn profile_data_dir(name: &str) -> PathBuf {
+	let mut path = roots().data.clone();
+	if name != DEFAULT_PROFILE {
+		path.push("profiles");
+		path.push(name);
+	}
+	path
+}
+
+pub fn profile_list_path(name: &str) -> PathBuf {
+	let mut path = profile_data_dir(name);
+	path.push("list");
+	path
+}
+
+pub fn profile_scripts_path(name: &str) -> PathBuf {
+	let mut path = roots().scripts.clone();
+	if name != DEFAULT_PROFILE {
+		path.push("profiles");
+		path.push(name);
+	}
+	path
+}
+
+// makes the directories for a profile, so it shows up in get_profiles
+pub fn create_profile(name: &str) -> Result<(), String> {
+	validate_profile_name(name)?;
+	fs::create_dir_all(profile_data_dir(name))
+		.map_err(|e| format!("Couldn't create profile \"{}\": {}", name, e))?;
+	fs::create_dir_all(profile_scripts_path(name))
+		.map_err(|e| format!("Couldn't create scripts directory for \"{}\": {}", name, e))?;
+	Ok(())
+}
+
+// the default profile, then every other one sorted by name
+pub fn get_profiles() -> Vec<String> {
+	let mut profiles: Vec<String> = {
+		let mut profiles_dir = roots().data.clone();
+		profiles_dir.push("profiles");
+		match fs::read_dir(profiles_dir) {
+			Ok(entries) => entries
+				.filter_map(|entry| entry.ok())
+				.filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+				.filter_map(|entry| entry.file_name().into_string().ok())
+				.filter(|name| validate_profile_name(name).is_ok() && name != DEFAULT_PROFILE)
+				.collect(),
+			Err(_) => vec![],
+		}
+	};
+	profiles.sort();
+	profiles.insert(0, DEFAULT_PROFILE.to_string());
+	profiles
+}
+
+pub fn current_profile() -> String {
+	let current = CURRENT.lock().unwrap();
+	if current.is_empty() {
+		DEFAULT_PROFILE.to_string()
+	}
+	else {
+		current.clone()
+	}
+}
+
+pub fn set_current_profile(name: &str) {
+	*CURRENT.lock().unwrap() = name.to_string();
+}
@@ -0,0 +1,140 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use log;
+
+use crate::landlock_sandbox::landlock_restrict_command;
+use crate::seccomp_sandbox::seccomp_restrict_command;
+use crate::spawner;
+
+// extra allowances for a provider script, read from "<script>.toml" next to it
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderManifest {
+	// paths the script may read
+	pub read: Vec<PathBuf>,
+	// paths the script may read and write
+	pub write: Vec<PathBuf>,
+	// whether the script may open TCP connections, if the main sandbox allows it
+	pub network: bool,
+}
+
+impl ProviderManifest {
+	fn manifest_path(script: &Path) -> PathBuf {
+		let mut path = script.as_os_str().to_owned();
+		path.push(".toml");
+		PathBuf::from(path)
+	}
+
+	pub fn load(script: &Path) -> Result<Self, String> {
+		let path = Self::manifest_path(script);
+		if !path.exists() {
+			return Ok(Self::default());
+		}
+
+		let manifest_str = fs::read_to_string(&path)
+			.map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+		toml::from_str(&manifest_str)
+			.map_err(|e| format!("Invalid manifest {}: {}", path.display(), e))
+	}
+}
+
+// manifests live in the scripts directory, but they aren't scripts
+pub fn is_manifest(path: &Path) -> bool {
+	path.extension().is_some_and(|ext| ext == "toml")
+}
+
+// makes a fresh private directory in $TMPDIR with an unguessable name
+// it's created exclusively, so an existing file or link there is an error, not something to reuse
+fn create_scratch_dir(script: &Path) -> io::Result<PathBuf> {
+	let name = script.file_name().unwrap_or_default().to_string_lossy();
+	let nonce = {
+		let mut h = RandomState::new().build_hasher();
+		process::id().hash(&mut h);
+		SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().hash(&mut h);
+		h.finish()
+	};
+	let mut scratch = env::temp_dir();
+	scratch.push(format!("todolist-{}-{:016x}", name, nonce));
+
+	let mut builder = DirBuilder::new();
+	#[cfg(unix)]
+	builder.mode(0o700);
+	builder.create(&scratch)?;
+	Ok(scratch)
+}
+
+// runs a provider script in its own landlock domain, with a private scratch directory
+// the script can't see the list or log files, only the system directories,
+// itself, its scratch directory and whatever its manifest allows
+// with sandboxed unset it just runs, scratch directory and all
+// once seccomp is on it's run by the spawner, since this process can't
+pub fn run_provider(script: &Path, sandboxed: bool) -> io::Result<Output> {
+	if let Some(output) = spawner::run_provider(script, sandboxed) {
+		return output;
+	}
+
+	let manifest = ProviderManifest::load(script)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+	let scratch = create_scratch_dir(script)?;
+
+	let mut command = Command::new(script);
+	command.current_dir(&scratch)
+		.env("TMPDIR", &scratch)
+		.stdin(Stdio::null());
+
+	let rw_paths = manifest.write.iter()
+		.map(|path| path.as_path())
+		.chain([scratch.as_path()])
+		.collect::<Vec<&Path>>();
+	let r_paths = manifest.read.iter()
+		.map(|path| path.as_path())
+		.chain([script])
+		.collect::<Vec<&Path>>();
+	let output = if !sandboxed {
+		command.output()
+	}
+	else {
+		match landlock_restrict_command(&mut command, &rw_paths, &r_paths, manifest.network) {
+			Ok(()) => {
+				// after landlock, so the filter doesn't have to allow landlock's own syscalls
+				seccomp_restrict_command(&mut command);
+				command.output()
+			},
+			Err(()) => Err(io::Error::new(io::ErrorKind::PermissionDenied, "couldn't sandbox script")),
+		}
+	};
+
+	if let Err(e) = fs::remove_dir_all(&scratch) {
+		log::warn!("Couldn't remove scratch directory {}: {}", scratch.display(), e);
+	}
+
+	output
+}
@@ -0,0 +1,64 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+// how well query matches text, None if it doesn't at all
+// every character of the query has to show up in text in order, ignoring case
+// runs of consecutive characters and matches at the start of words score higher,
+// so "lab3" finds "Lab 3" before "Lecture about bees, 3"
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+	let query = query.to_lowercase()
+		.chars()
+		.filter(|c| !c.is_whitespace())
+		.collect::<Vec<char>>();
+	if query.is_empty() {
+		return Some(0);
+	}
+
+	let text = text.chars().collect::<Vec<char>>();
+	let mut score = 0;
+	let mut next = 0;
+	let mut last_match: Option<usize> = None;
+	for (i, c) in text.iter().enumerate() {
+		if !c.to_lowercase().eq(query[next].to_lowercase()) {
+			continue;
+		}
+
+		score += 1;
+		if last_match.is_some_and(|last| last + 1 == i) {
+			score += 5;
+		}
+		let word_start = i == 0 || !text[i - 1].is_alphanumeric();
+		if word_start {
+			score += 3;
+		}
+		last_match = Some(i);
+
+		next += 1;
+		if next == query.len() {
+			// shorter texts are the closer match
+			return Some(score * 100 - text.len() as i64);
+		}
+	}
+
+	None
+}
+
+// whether anything in texts matches the query
+pub fn fuzzy_matches(query: &str, texts: &[&str]) -> bool {
+	texts.iter().any(|text| fuzzy_score(query, text).is_some())
+}
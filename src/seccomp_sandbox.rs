@@ -0,0 +1,202 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(target_os = "linux")]
+use seccompiler::{
+	BpfProgram, SeccompAction, SeccompFilter, TargetArch,
+	apply_filter, apply_filter_all_threads,
+};
+#[cfg(target_os = "linux")]
+use std::collections::BTreeMap;
+#[cfg(target_os = "linux")]
+use std::io;
+#[cfg(target_os = "linux")]
+use std::os::unix::process::CommandExt;
+#[cfg(target_os = "linux")]
+use std::sync::OnceLock;
+
+use std::process::Command;
+
+// what the TUI and saving the list need
+// anything else fails with EPERM instead of killing the process,
+// so a missed syscall shows up as an error rather than a wrecked terminal
+#[cfg(target_os = "linux")]
+const MAIN_SYSCALLS: &[libc::c_long] = &[
+	libc::SYS_read, libc::SYS_write, libc::SYS_readv, libc::SYS_writev,
+	libc::SYS_pread64, libc::SYS_pwrite64, libc::SYS_lseek,
+	libc::SYS_openat, libc::SYS_close, libc::SYS_fstat, libc::SYS_newfstatat, libc::SYS_statx,
+	libc::SYS_getdents64, libc::SYS_readlinkat, libc::SYS_faccessat, libc::SYS_faccessat2,
+	libc::SYS_mkdirat, libc::SYS_unlinkat, libc::SYS_renameat, libc::SYS_renameat2,
+	libc::SYS_ftruncate, libc::SYS_fsync, libc::SYS_fdatasync, libc::SYS_flock,
+	libc::SYS_fcntl, libc::SYS_dup, libc::SYS_dup3, libc::SYS_pipe2, libc::SYS_ioctl,
+	libc::SYS_ppoll, libc::SYS_pselect6, libc::SYS_epoll_create1, libc::SYS_epoll_ctl,
+	libc::SYS_epoll_pwait, libc::SYS_eventfd2,
+	libc::SYS_inotify_init1, libc::SYS_inotify_add_watch, libc::SYS_inotify_rm_watch,
+	// the command socket, and CalDAV and `todolist serve`; which addresses are reachable is up to Landlock
+	libc::SYS_socket, libc::SYS_connect, libc::SYS_shutdown,
+	libc::SYS_bind, libc::SYS_listen, libc::SYS_accept, libc::SYS_accept4,
+	libc::SYS_getsockname, libc::SYS_getpeername, libc::SYS_getsockopt, libc::SYS_setsockopt,
+	libc::SYS_sendto, libc::SYS_recvfrom, libc::SYS_sendmsg, libc::SYS_recvmsg, libc::SYS_sendmmsg,
+	libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mprotect, libc::SYS_mremap,
+	libc::SYS_madvise, libc::SYS_brk,
+	libc::SYS_rt_sigaction, libc::SYS_rt_sigprocmask, libc::SYS_rt_sigreturn,
+	libc::SYS_sigaltstack, libc::SYS_kill, libc::SYS_tgkill,
+	libc::SYS_futex, libc::SYS_clone, libc::SYS_clone3, libc::SYS_set_robust_list,
+	libc::SYS_rseq, libc::SYS_sched_yield, libc::SYS_sched_getaffinity,
+	libc::SYS_getpid, libc::SYS_gettid, libc::SYS_getuid, libc::SYS_geteuid,
+	libc::SYS_getcwd, libc::SYS_uname, libc::SYS_prlimit64, libc::SYS_getrandom,
+	libc::SYS_clock_gettime, libc::SYS_clock_nanosleep, libc::SYS_nanosleep,
+	libc::SYS_exit, libc::SYS_exit_group,
+	#[cfg(target_arch = "x86_64")] libc::SYS_open,
+	#[cfg(target_arch = "x86_64")] libc::SYS_stat,
+	#[cfg(target_arch = "x86_64")] libc::SYS_lstat,
+	#[cfg(target_arch = "x86_64")] libc::SYS_poll,
+	#[cfg(target_arch = "x86_64")] libc::SYS_select,
+	#[cfg(target_arch = "x86_64")] libc::SYS_epoll_wait,
+	#[cfg(target_arch = "x86_64")] libc::SYS_pipe,
+	#[cfg(target_arch = "x86_64")] libc::SYS_dup2,
+	#[cfg(target_arch = "x86_64")] libc::SYS_access,
+	#[cfg(target_arch = "x86_64")] libc::SYS_readlink,
+	#[cfg(target_arch = "x86_64")] libc::SYS_mkdir,
+	#[cfg(target_arch = "x86_64")] libc::SYS_unlink,
+	#[cfg(target_arch = "x86_64")] libc::SYS_rename,
+];
+
+// on top of MAIN_SYSCALLS, what a provider script (usually a shell, maybe curl) needs
+#[cfg(target_os = "linux")]
+const SCRIPT_SYSCALLS: &[libc::c_long] = &[
+	libc::SYS_execve, libc::SYS_execveat, libc::SYS_wait4, libc::SYS_waitid,
+	libc::SYS_set_tid_address, libc::SYS_prctl, libc::SYS_chdir, libc::SYS_fchdir,
+	libc::SYS_umask, libc::SYS_statfs, libc::SYS_fstatfs, libc::SYS_fchmod, libc::SYS_fchmodat,
+	libc::SYS_getppid, libc::SYS_getpgid, libc::SYS_setpgid, libc::SYS_getsid,
+	libc::SYS_getgid, libc::SYS_getegid, libc::SYS_getgroups, libc::SYS_getresuid, libc::SYS_getresgid,
+	libc::SYS_getrusage, libc::SYS_times, libc::SYS_sysinfo, libc::SYS_setitimer, libc::SYS_getitimer,
+	libc::SYS_socket, libc::SYS_socketpair, libc::SYS_connect, libc::SYS_bind,
+	libc::SYS_getsockname, libc::SYS_getpeername, libc::SYS_getsockopt, libc::SYS_setsockopt,
+	libc::SYS_sendto, libc::SYS_recvfrom, libc::SYS_sendmsg, libc::SYS_recvmsg,
+	libc::SYS_sendmmsg, libc::SYS_shutdown,
+	libc::SYS_symlinkat, libc::SYS_linkat, libc::SYS_utimensat, libc::SYS_fchownat,
+	#[cfg(target_arch = "x86_64")] libc::SYS_fork,
+	#[cfg(target_arch = "x86_64")] libc::SYS_vfork,
+	#[cfg(target_arch = "x86_64")] libc::SYS_arch_prctl,
+	#[cfg(target_arch = "x86_64")] libc::SYS_getpgrp,
+	#[cfg(target_arch = "x86_64")] libc::SYS_alarm,
+	#[cfg(target_arch = "x86_64")] libc::SYS_chmod,
+	#[cfg(target_arch = "x86_64")] libc::SYS_symlink,
+	#[cfg(target_arch = "x86_64")] libc::SYS_link,
+];
+
+// on top of both, what the spawner needs to sandbox the scripts it runs
+#[cfg(target_os = "linux")]
+const SPAWNER_SYSCALLS: &[libc::c_long] = &[
+	libc::SYS_landlock_create_ruleset, libc::SYS_landlock_add_rule, libc::SYS_landlock_restrict_self,
+	libc::SYS_seccomp,
+];
+
+#[cfg(target_os = "linux")]
+struct Profiles {
+	main: BpfProgram,
+	script: BpfProgram,
+	spawner: BpfProgram,
+}
+
+#[cfg(target_os = "linux")]
+static PROFILES: OnceLock<Profiles> = OnceLock::new();
+
+#[cfg(target_os = "linux")]
+fn compile_profile(syscalls: &[&[libc::c_long]]) -> Result<BpfProgram, String> {
+	let arch = TargetArch::try_from(std::env::consts::ARCH)
+		.map_err(|e| e.to_string())?;
+	let rules = syscalls.iter()
+		.flat_map(|syscalls| syscalls.iter())
+		.map(|syscall| (*syscall, vec![]))
+		.collect::<BTreeMap<_, _>>();
+	let filter = SeccompFilter::new(rules,
+		SeccompAction::Errno(libc::EPERM as u32),
+		SeccompAction::Allow,
+		arch).map_err(|e| e.to_string())?;
+	filter.try_into()
+		.map_err(|e: seccompiler::BackendError| e.to_string())
+}
+
+// compiles the profiles, after this provider scripts get the script profile
+// the main profile isn't installed until seccomp_restrict is called,
+// since a process can't loosen its own filter for its children
+// it never allows running anything, git and scripts go through the spawner for that
+#[cfg(target_os = "linux")]
+pub fn seccomp_enable() -> Result<(), String> {
+	let profiles = Profiles {
+		main: compile_profile(&[MAIN_SYSCALLS])?,
+		script: compile_profile(&[MAIN_SYSCALLS, SCRIPT_SYSCALLS])?,
+		spawner: compile_profile(&[MAIN_SYSCALLS, SCRIPT_SYSCALLS, SPAWNER_SYSCALLS])?,
+	};
+	let _ = PROFILES.set(profiles);
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn seccomp_enable() -> Result<(), String> {
+	Err("seccomp is only available on Linux".to_string())
+}
+
+// installs the main profile on every thread of this process
+// nothing can be spawned afterwards, so the spawner has to be started first
+#[cfg(target_os = "linux")]
+pub fn seccomp_restrict() -> Result<(), String> {
+	match PROFILES.get() {
+		Some(profiles) => apply_filter_all_threads(&profiles.main)
+			.map_err(|e| e.to_string()),
+		None => Ok(()),
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn seccomp_restrict() -> Result<(), String> {
+	Ok(())
+}
+
+// installs the script profile in a child process between fork and exec
+// does nothing unless seccomp_enable was called
+#[cfg(target_os = "linux")]
+pub fn seccomp_restrict_command(command: &mut Command) {
+	if let Some(profiles) = PROFILES.get() {
+		restrict_command(command, &profiles.script);
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn seccomp_restrict_command(_command: &mut Command) {}
+
+// the same with the spawner profile, for the spawner itself
+#[cfg(target_os = "linux")]
+pub fn seccomp_restrict_spawner(command: &mut Command) {
+	if let Some(profiles) = PROFILES.get() {
+		restrict_command(command, &profiles.spawner);
+	}
+}
+
+#[cfg(target_os = "linux")]
+fn restrict_command(command: &mut Command, program: &'static BpfProgram) {
+	// this runs in the child between fork and exec, so it can't log or allocate
+	unsafe {
+		command.pre_exec(move || {
+			apply_filter(program)
+				.map_err(|_| io::Error::from(io::ErrorKind::PermissionDenied))
+		});
+	}
+}
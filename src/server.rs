@@ -0,0 +1,350 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+use std::fs::File;
+use std::io::Read;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+use log;
+
+use crate::assignment::Assignment;
+use crate::config::ServerConfig;
+use crate::merge::Resolution;
+use crate::todolist::{SaveError, TodoList};
+
+// request bodies bigger than this are refused
+const MAX_BODY: u64 = 64 * 1024;
+
+#[derive(Serialize)]
+struct AssignmentJson {
+	// the uid in hex, since JavaScript can't hold a u64
+	// it changes whenever the assignment is edited, like the uid does
+	id: String,
+	class: String,
+	name: String,
+	// null for assignments without a due date
+	due: Option<DateTime<Local>>,
+	completed: bool,
+	tags: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	source: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewClass {
+	name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewAssignment {
+	name: String,
+	#[serde(default)]
+	due: Option<DateTime<Local>>,
+	#[serde(default)]
+	completed: bool,
+	#[serde(default)]
+	tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AssignmentUpdate {
+	name: Option<String>,
+	// missing leaves the due date alone, null takes it away
+	#[serde(default, deserialize_with = "present")]
+	due: Option<Option<DateTime<Local>>>,
+	completed: Option<bool>,
+	tags: Option<Vec<String>>,
+}
+
+// tells a field that's there but null apart from one that isn't there
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+	where D: Deserializer<'de>, T: Deserialize<'de>
+{
+	T::deserialize(deserializer).map(Some)
+}
+
+// a status and a JSON body, errors being {"error": "..."}
+type Reply = (u16, serde_json::Value);
+
+fn error(status: u16, message: &str) -> Reply {
+	(status, json!({ "error": message }))
+}
+
+fn assignment_json(todolist: &TodoList, uid: u64) -> Option<AssignmentJson> {
+	let assign = todolist.get_assignment(uid).ok()?;
+	Some(AssignmentJson {
+		id: format!("{:016x}", uid),
+		class: todolist.get_assignment_class(uid)?,
+		name: assign.name,
+		due: assign.due_date,
+		completed: todolist.get_assignment_completion(uid).ok()?,
+		tags: assign.tags,
+		source: assign.source.map(|source| source.script),
+	})
+}
+
+fn list_assignments(todolist: &TodoList, class: Option<&str>) -> Reply {
+	let assignments = todolist.snapshot()
+		.into_iter()
+		.filter(|(classname, _)| class.is_none_or(|class| class == classname))
+		.flat_map(|(_, assignments)| assignments.into_values())
+		.filter_map(|(assign, _)| assignment_json(todolist, TodoList::hash_assignment(&assign)))
+		.collect::<Vec<_>>();
+	(200, json!(assignments))
+}
+
+fn parse_id(id: &str) -> Option<u64> {
+	u64::from_str_radix(id, 16).ok()
+}
+
+fn parse_body<T>(body: &str) -> Result<T, Reply>
+	where T: for<'a> Deserialize<'a>
+{
+	serde_json::from_str(body)
+		.map_err(|e| error(400, &format!("Bad request body: {}", e)))
+}
+
+// "%20" and friends in class names
+fn percent_decode(segment: &str) -> Option<String> {
+	let bytes = segment.as_bytes();
+	let mut decoded = vec![];
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' {
+			let hex = segment.get(i + 1..i + 3)?;
+			decoded.push(u8::from_str_radix(hex, 16).ok()?);
+			i += 3;
+		}
+		else {
+			decoded.push(bytes[i]);
+			i += 1;
+		}
+	}
+	String::from_utf8(decoded).ok()
+}
+
+fn route(todolist: &mut TodoList, method: &Method, path: &[String], body: &str) -> Result<Reply, Reply> {
+	let path = path.iter()
+		.map(String::as_str)
+		.collect::<Vec<&str>>();
+	let reply = match (method, path.as_slice()) {
+		(Method::Get, ["classes"]) => (200, json!(todolist.get_classes())),
+		(Method::Post, ["classes"]) => {
+			let class: NewClass = parse_body(body)?;
+			if class.name.is_empty() {
+				return Err(error(400, "Class names can't be empty"));
+			}
+			todolist.create_class(class.name.clone())
+				.map_err(|()| error(409, "There's a class with that name already"))?;
+			(201, json!(class.name))
+		},
+		(Method::Delete, ["classes", class]) => {
+			todolist.delete_class(class.to_string())
+				.map_err(|()| error(404, "No such class"))?;
+			(204, json!(null))
+		},
+		(Method::Get, ["classes", class, "assignments"]) => {
+			if !todolist.get_classes().iter().any(|classname| classname == class) {
+				return Err(error(404, "No such class"));
+			}
+			list_assignments(todolist, Some(class))
+		},
+		(Method::Post, ["classes", class, "assignments"]) => {
+			let new: NewAssignment = parse_body(body)?;
+			let assignment = Assignment {
+				due_date: new.due,
+				name: new.name,
+				source: None,
+				tags: Assignment::parse_tags(&new.tags.join(" ")),
+				id: 0,
+			};
+			let uid = todolist.create_assignment(class.to_string(), assignment)
+				.map_err(|()| error(409, "No such class, or the assignment exists already"))?;
+			if new.completed {
+				let _ = todolist.set_assignment_completion(uid, true);
+			}
+			(201, json!(assignment_json(todolist, uid)))
+		},
+		(Method::Get, ["assignments"]) => list_assignments(todolist, None),
+		(Method::Get, ["assignments", id]) => {
+			let assignment = parse_id(id)
+				.and_then(|uid| assignment_json(todolist, uid))
+				.ok_or_else(|| error(404, "No such assignment"))?;
+			(200, json!(assignment))
+		},
+		(Method::Patch, ["assignments", id]) => {
+			let update: AssignmentUpdate = parse_body(body)?;
+			let uid = parse_id(id)
+				.ok_or_else(|| error(404, "No such assignment"))?;
+			let mut assignment = todolist.get_assignment(uid)
+				.map_err(|()| error(404, "No such assignment"))?;
+			if let Some(name) = update.name {
+				assignment.name = name;
+			}
+			if let Some(due) = update.due {
+				assignment.due_date = due;
+			}
+			if let Some(tags) = update.tags {
+				assignment.tags = Assignment::parse_tags(&tags.join(" "));
+			}
+			let uid = todolist.edit_assignment(uid, assignment)
+				.map_err(|()| error(409, "An identical assignment exists already"))?;
+			if let Some(completed) = update.completed {
+				let _ = todolist.set_assignment_completion(uid, completed);
+			}
+			(200, json!(assignment_json(todolist, uid)))
+		},
+		(Method::Delete, ["assignments", id]) => {
+			parse_id(id)
+				.and_then(|uid| todolist.delete_assignment(uid).ok())
+				.ok_or_else(|| error(404, "No such assignment"))?;
+			(204, json!(null))
+		},
+		(Method::Post | Method::Delete, ["assignments", id, "complete"]) => {
+			let uid = parse_id(id)
+				.ok_or_else(|| error(404, "No such assignment"))?;
+			todolist.set_assignment_completion(uid, *method == Method::Post)
+				.map_err(|()| error(404, "No such assignment"))?;
+			(200, json!(assignment_json(todolist, uid)))
+		},
+		(_, ["classes"] | ["classes", _] | ["classes", _, "assignments"] | ["assignments"] | ["assignments", _] | ["assignments", _, "complete"]) => {
+			error(405, "Method not allowed")
+		},
+		_ => error(404, "Not found"),
+	};
+	Ok(reply)
+}
+
+// saves what a request changed, merging with whatever the TUI or anyone else saved
+// on a real conflict the request wins, it's the newest thing anyone asked for
+fn save(todolist: &mut TodoList) -> Result<(), SaveError> {
+	match todolist.save_to_file() {
+		Err(SaveError::Conflicts(n)) => {
+			log::warn!("Keeping the request's side of {} conflict(s)", n);
+			for i in 0..n {
+				let _ = todolist.resolve_conflict(i, Resolution::Ours);
+			}
+			todolist.finish_merge().map(|_| ())
+		},
+		res => res.map(|_| ()),
+	}
+}
+
+fn handle(todolist: &mut TodoList, token: &str, request: &mut Request) -> Reply {
+	let authorized = request.headers().iter()
+		.filter(|header| header.field.equiv("Authorization"))
+		.any(|header| header.value.as_str().strip_prefix("Bearer ").is_some_and(|given| tokens_match(given, token)));
+	if !authorized {
+		return error(401, "Missing or wrong token");
+	}
+
+	if request.body_length().is_some_and(|length| length as u64 > MAX_BODY) {
+		return error(413, "Request body too large");
+	}
+	// a chunked body has no length up front, one byte too many gives it away
+	let mut body = vec![];
+	if let Err(e) = request.as_reader().take(MAX_BODY + 1).read_to_end(&mut body) {
+		return error(400, &format!("Couldn't read request body: {}", e));
+	}
+	if body.len() as u64 > MAX_BODY {
+		return error(413, "Request body too large");
+	}
+	let Ok(body) = String::from_utf8(body) else {
+		return error(400, "Request body isn't UTF-8");
+	};
+
+	let path = request.url()
+		.split('?')
+		.next()
+		.unwrap_or("")
+		.split('/')
+		.filter(|segment| !segment.is_empty())
+		.map(percent_decode)
+		.collect::<Option<Vec<String>>>();
+	let Some(path) = path else {
+		return error(400, "Bad URL");
+	};
+
+	// pick up anything the TUI saved since the last request
+	if let Err(e) = todolist.reload() {
+		log::warn!("Couldn't reload list: {}", e);
+	}
+
+	let reply = route(todolist, request.method(), &path, &body)
+		.unwrap_or_else(|reply| reply);
+	if todolist.is_dirty() {
+		if let Err(e) = save(todolist) {
+			log::error!("Failed to save list: {}", e);
+			return error(500, &format!("Failed to save list: {}", e));
+		}
+	}
+	reply
+}
+
+// compares every byte, so how long it took doesn't give away how much matched
+fn tokens_match(given: &str, token: &str) -> bool {
+	given.len() == token.len() &&
+		given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn random_token() -> Result<String, String> {
+	let mut bytes = [0u8; 16];
+	File::open("/dev/urandom")
+		.and_then(|mut urandom| urandom.read_exact(&mut bytes))
+		.map_err(|e| format!("Couldn't make up a token: {}", e))?;
+	Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// serves the list over HTTP until killed
+pub fn serve(mut todolist: TodoList, server_config: &ServerConfig) -> Result<(), String> {
+	let token = match &server_config.token {
+		Some(token) => token.clone(),
+		None => {
+			let token = random_token()?;
+			println!("Token: {}", token);
+			token
+		},
+	};
+
+	let server = Server::http(&server_config.bind)
+		.map_err(|e| format!("Couldn't listen on {}: {}", server_config.bind, e))?;
+	println!("Listening on http://{}", server_config.bind);
+	log::info!("Serving the list on {}", server_config.bind);
+
+	let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+	for mut request in server.incoming_requests() {
+		let (status, body) = handle(&mut todolist, &token, &mut request);
+		log::info!("{} {} -> {}", request.method(), request.url(), status);
+		let response = if status == 204 {
+			Response::from_string("")
+		}
+		else {
+			Response::from_string(body.to_string())
+				.with_header(content_type.clone())
+		};
+		if let Err(e) = request.respond(response.with_status_code(status)) {
+			log::warn!("Couldn't respond: {}", e);
+		}
+	}
+	Ok(())
+}
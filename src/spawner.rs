@@ -0,0 +1,224 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+// once the main seccomp profile is on, this process can't run anything itself,
+// so git and the provider scripts are run by a helper started before that:
+// another todolist process, reading requests on stdin and answering on stdout, a JSON line each
+
+#[cfg(target_os = "linux")]
+use std::env;
+#[cfg(target_os = "linux")]
+use std::io::{self, BufRead, BufReader, Write};
+#[cfg(target_os = "linux")]
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::process::Output;
+#[cfg(target_os = "linux")]
+use std::sync::Mutex;
+#[cfg(target_os = "linux")]
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use log;
+
+#[cfg(target_os = "linux")]
+use crate::config::GitConfig;
+#[cfg(target_os = "linux")]
+use crate::git;
+#[cfg(target_os = "linux")]
+use crate::provider;
+#[cfg(target_os = "linux")]
+use crate::seccomp_sandbox::{seccomp_enable, seccomp_restrict_spawner};
+
+#[cfg(target_os = "linux")]
+#[derive(Serialize, Deserialize)]
+enum Request {
+	Provider {
+		script: PathBuf,
+		sandboxed: bool,
+	},
+	Commit {
+		list_path: PathBuf,
+		message: String,
+	},
+}
+
+// an Output that can be sent over, the status being the raw wait status
+#[cfg(target_os = "linux")]
+#[derive(Serialize, Deserialize)]
+struct RawOutput {
+	status: i32,
+	stdout: Vec<u8>,
+	stderr: Vec<u8>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Serialize, Deserialize)]
+enum Response {
+	Provider(Result<RawOutput, String>),
+	Commit(Result<(), String>),
+}
+
+#[cfg(target_os = "linux")]
+struct Spawner {
+	// kept so it's waited for on exit, it quits once its stdin closes
+	_child: Child,
+	stdin: ChildStdin,
+	stdout: BufReader<ChildStdout>,
+}
+
+// the running helper, None in the helper itself and whenever seccomp is off
+#[cfg(target_os = "linux")]
+static SPAWNER: Mutex<Option<Spawner>> = Mutex::new(None);
+
+// starts the helper with the spawner profile, has to come before seccomp_restrict
+// it's in the same landlock domain as this process, and the scripts it runs get their own as always
+#[cfg(target_os = "linux")]
+pub fn start_spawner() -> Result<(), String> {
+	let exe = env::current_exe()
+		.map_err(|e| format!("Couldn't find the todolist executable: {}", e))?;
+	let mut command = Command::new(exe);
+	command.arg("spawner")
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null());
+	seccomp_restrict_spawner(&mut command);
+
+	let mut child = command.spawn()
+		.map_err(|e| format!("Couldn't start the spawner: {}", e))?;
+	let stdin = child.stdin.take()
+		.ok_or("The spawner has no stdin")?;
+	let stdout = child.stdout.take()
+		.ok_or("The spawner has no stdout")?;
+	*SPAWNER.lock().unwrap() = Some(Spawner {
+		_child: child,
+		stdin,
+		stdout: BufReader::new(stdout),
+	});
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start_spawner() -> Result<(), String> {
+	Err("the spawner is only needed with seccomp, which is only available on Linux".to_string())
+}
+
+// None if there's no helper, so the caller runs it itself
+#[cfg(target_os = "linux")]
+fn request(request: &Request) -> Option<Result<Response, String>> {
+	let mut spawner = SPAWNER.lock().unwrap();
+	let spawner = spawner.as_mut()?;
+
+	let mut response = String::new();
+	let res = serde_json::to_string(request)
+		.map_err(io::Error::other)
+		.and_then(|request| writeln!(spawner.stdin, "{}", request))
+		.and_then(|()| spawner.stdin.flush())
+		.and_then(|()| spawner.stdout.read_line(&mut response));
+	Some(match res {
+		Ok(0) => Err("The spawner exited".to_string()),
+		Ok(_) => serde_json::from_str(&response)
+			.map_err(|e| format!("Invalid answer from the spawner: {}", e)),
+		Err(e) => Err(format!("Couldn't reach the spawner: {}", e)),
+	})
+}
+
+// runs a provider script through the helper, None if there's no helper
+#[cfg(target_os = "linux")]
+pub fn run_provider(script: &Path, sandboxed: bool) -> Option<io::Result<Output>> {
+	let response = request(&Request::Provider {
+		script: script.to_path_buf(),
+		sandboxed,
+	})?;
+	Some(match response {
+		Ok(Response::Provider(Ok(output))) => Ok(Output {
+			status: ExitStatus::from_raw(output.status),
+			stdout: output.stdout,
+			stderr: output.stderr,
+		}),
+		Ok(Response::Provider(Err(e))) => Err(io::Error::other(e)),
+		Ok(_) => Err(io::Error::other("Wrong answer from the spawner")),
+		Err(e) => Err(io::Error::other(e)),
+	})
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn run_provider(_script: &Path, _sandboxed: bool) -> Option<std::io::Result<Output>> {
+	None
+}
+
+// commits through the helper, None if there's no helper
+#[cfg(target_os = "linux")]
+pub fn commit(list_path: &Path, message: &str) -> Option<Result<(), String>> {
+	let response = request(&Request::Commit {
+		list_path: list_path.to_path_buf(),
+		message: message.to_string(),
+	})?;
+	Some(match response {
+		Ok(Response::Commit(res)) => res,
+		Ok(_) => Err("Wrong answer from the spawner".to_string()),
+		Err(e) => Err(e),
+	})
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn commit(_list_path: &Path, _message: &str) -> Option<Result<(), String>> {
+	None
+}
+
+// the helper's side, answers requests until the application closes its stdin
+#[cfg(target_os = "linux")]
+pub fn serve_spawner(git_config: &GitConfig) {
+	// only for the script profile, the spawner profile is already on
+	if let Err(e) = seccomp_enable() {
+		log::error!("Spawner couldn't set up seccomp, not running anything: {}", e);
+		return;
+	}
+
+	let mut stdout = io::stdout().lock();
+	for line in io::stdin().lock().lines() {
+		let Ok(line) = line else {
+			break;
+		};
+		let response = match serde_json::from_str(&line) {
+			Ok(Request::Provider { script, sandboxed }) => Response::Provider(provider::run_provider(&script, sandboxed)
+				.map(|output| RawOutput {
+					status: output.status.into_raw(),
+					stdout: output.stdout,
+					stderr: output.stderr,
+				})
+				.map_err(|e| e.to_string())),
+			Ok(Request::Commit { list_path, message }) => Response::Commit(git::commit(&list_path, &message, git_config)),
+			Err(e) => {
+				log::error!("Spawner got an invalid request: {}", e);
+				break;
+			},
+		};
+		let res = writeln!(stdout, "{}", serde_json::to_string(&response).unwrap())
+			.and_then(|()| stdout.flush());
+		if res.is_err() {
+			break;
+		}
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn serve_spawner(_git_config: &crate::config::GitConfig) {}
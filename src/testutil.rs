@@ -0,0 +1,44 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+// what the tests share: one fixed "now" and one way to make an assignment
+
+use chrono::{DateTime, Local, TimeDelta, TimeZone};
+
+use crate::assignment::Assignment;
+
+pub fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+	Local.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+}
+
+// a Sunday evening
+pub fn now() -> DateTime<Local> {
+	at(2026, 10, 18, 18, 0)
+}
+
+// due that many days from now, or never, with the tags as they'd be typed
+// the id is left for the list to give it, like for a new one
+pub fn assignment(name: &str, days_from_now: Option<i64>, tags: &str) -> Assignment {
+	Assignment {
+		due_date: days_from_now.map(|days| now() + TimeDelta::days(days)),
+		name: name.to_string(),
+		source: None,
+		tags: Assignment::parse_tags(tags),
+		id: 0,
+	}
+}
@@ -17,19 +17,29 @@ along with this program; if not, see
 */
 
 use std::vec::Vec;
-use std::collections::{HashMap,BTreeMap};
+use std::collections::{HashMap,HashSet,BTreeMap};
+use std::collections::hash_map::{Entry, RandomState};
 use std::path::{Path, PathBuf};
 use std::fs::{self,File};
-use std::io::{prelude::*, BufReader, BufWriter};
-use chrono::{NaiveDate, NaiveTime, NaiveDateTime, Local};
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::time::{Duration, Instant};
+use std::fmt;
+use chrono::{DateTime, NaiveDate, NaiveTime, NaiveDateTime, Local};
 use chrono::offset::MappedLocalTime;
-use std::hash::{DefaultHasher, Hash, Hasher};
-use std::process::Command;
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher};
 use serde::{Deserialize, Serialize};
 use log;
 
 use crate::assignment::Assignment;
 use crate::assignment::AssignmentV1;
+use crate::assignment::ExternalSource;
+use crate::provider::{is_manifest, run_provider};
+use crate::landlock_sandbox::script_sandbox_enabled;
+use crate::history::{Change, History};
+use crate::config::config;
+use crate::git;
+use crate::caldav::CALDAV_SOURCE;
+use crate::merge::{merge, apply_resolution, Conflict, Resolution, Snapshot};
 
 pub struct TodoList {
 	// class name to assignment ids
@@ -37,9 +47,52 @@ pub struct TodoList {
 	assignment_by_uid: HashMap<u64, Assignment>,
 	completed_by_uid: HashMap<u64, bool>,
 	ghost_uids: Vec<u64>,
+	// externally managed assignments their script no longer reports
+	removed_uids: Vec<u64>,
+	history: History,
+	// whether there are changes that haven't been saved, and when the last one was
+	dirty: bool,
+	changed_at: Option<Instant>,
+	// what's changed since the last save, for the git commit message
+	unsaved_changes: Vec<String>,
+	// what the list file held when it was last loaded or saved, to merge against
+	base: Snapshot,
+	base_hash: u64,
+	pending_merge: Option<PendingMerge>,
 	list_path: PathBuf,
 }
 
+// a merge with the list file that's waiting for the user to resolve its conflicts
+#[derive(Clone)]
+struct PendingMerge {
+	merged: Snapshot,
+	theirs: Snapshot,
+	theirs_hash: u64,
+	conflicts: Vec<Conflict>,
+	resolutions: Vec<Option<Resolution>>,
+}
+
+pub enum SaveError {
+	Io(io::Error),
+	// the list file was changed by someone else in ways that clash with ours
+	Conflicts(usize),
+}
+
+impl fmt::Display for SaveError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SaveError::Io(e) => write!(f, "{}", e),
+			SaveError::Conflicts(n) => write!(f, "{} assignment(s) were changed both here and in the list file", n),
+		}
+	}
+}
+
+impl From<io::Error> for SaveError {
+	fn from(e: io::Error) -> Self {
+		SaveError::Io(e)
+	}
+}
+
 type TodoListV1 = BTreeMap<String, Vec<AssignmentV1>>;
 #[derive(Serialize, Deserialize)]
 struct TodoListV2 {
@@ -57,96 +110,400 @@ impl TodoList {
 	pub fn new<P>(load_path: P, script_path: P) -> Result<Self,&'static str>
 		where P: AsRef<Path>
 	{
-		if load_path.as_ref().exists() && load_path.as_ref().is_file() {
-			let list_str = TodoList::read_file_sans_newline(&load_path);
-			let parsed;
-			if let Ok(v1) = Self::parse_v1(&list_str) {
-				parsed = v1;
-			}
-			else if let Ok(v2) = Self::parse_v2(&list_str) {
-				parsed = v2;
+		let mut todolist = if load_path.as_ref().exists() && load_path.as_ref().is_file() {
+			let list_str = {
+				let _lock = Self::lock_list(load_path.as_ref(), false);
+				TodoList::read_file_sans_newline(&load_path)
+			};
+			let parsed = Self::parse_str(&list_str)?;
+			let base = Self::snapshot_of(&parsed);
+
+			Self {
+				uids_by_class: parsed.uids_by_class,
+				assignment_by_uid: parsed.assignment_by_uid,
+				completed_by_uid: parsed.completed_by_uid,
+				ghost_uids: Vec::new(),
+				removed_uids: Vec::new(),
+				history: History::new(config().history.limit),
+				dirty: false,
+				changed_at: None,
+				unsaved_changes: Vec::new(),
+				base,
+				base_hash: Self::hash_str(&list_str),
+				pending_merge: None,
+				list_path: PathBuf::from(load_path.as_ref()),
 			}
-			else {
-				return Err("Failed to parse log file, pretending it's blank");
+		}
+		else {
+			log::info!("Couldn't read todolist at {}, creating blank one", load_path.as_ref().display());
+			let _ = File::create_new(&load_path);
+			Self {
+				uids_by_class: HashMap::new(),
+				assignment_by_uid: HashMap::new(),
+				completed_by_uid: HashMap::new(),
+				ghost_uids: Vec::new(),
+				removed_uids: Vec::new(),
+				history: History::new(config().history.limit),
+				dirty: false,
+				changed_at: None,
+				unsaved_changes: Vec::new(),
+				base: Snapshot::new(),
+				base_hash: Self::hash_str(""),
+				pending_merge: None,
+				list_path: PathBuf::from(load_path.as_ref()),
 			}
+		};
 
-			let mut uids_by_class = parsed.uids_by_class;
-			let mut assignment_by_uid = parsed.assignment_by_uid;
-			let mut completed_by_uid = parsed.completed_by_uid;
-			let mut ghost_uids = Vec::new();
+		if script_path.as_ref().exists() && script_path.as_ref().is_dir() {
+			todolist.load_scripts(script_path.as_ref());
+		}
 
-			if script_path.as_ref().exists() && script_path.as_ref().is_dir() {
-				fs::read_dir(script_path)
-					.unwrap()
-					.for_each(|entry| {
-						let entry = entry.unwrap();
+		if config().history.persist {
+			todolist.history = History::load(&todolist.history_path(), config().history.limit);
+		}
+
+		Ok(todolist)
+	}
+
+	// runs every provider script and merges their output into the list
+	// each output line is "class,name,YYYY-MM-DD,HH:MM" with an optional ",id" at the end
+	// lines without an id are ghosts, which only get saved once completed
+	// lines with an id are tracked by (script, id) and get updated in place
+	fn load_scripts(&mut self, script_path: &Path) {
+		let mut seen = HashSet::new();
+		let mut failed_scripts = HashSet::new();
 
-						if !entry.file_type().unwrap().is_file() {
-							return;
+		fs::read_dir(script_path)
+			.unwrap()
+			.for_each(|entry| {
+				let entry = entry.unwrap();
+
+				if !entry.file_type().unwrap().is_file() || is_manifest(&entry.path()) {
+					return;
+				}
+
+				let script = entry.file_name().to_string_lossy().into_owned();
+				// a failing script still gets its output read, it just can't flag removals
+				let output = match run_provider(&entry.path(), script_sandbox_enabled()) {
+					Ok(output) => {
+						if !output.status.success() {
+							log::warn!("Provider script {} exited with {}", script, output.status);
+							failed_scripts.insert(script.clone());
 						}
+						output
+					},
+					Err(e) => {
+						log::warn!("Provider script {} failed to run: {}", script, e);
+						failed_scripts.insert(script);
+						return;
+					},
+				};
 
-						if let Ok(output) = Command::new(entry.path()).output() {
-							if let Ok(lines) = String::from_utf8(output.stdout) {
-								lines.lines()
-									.for_each(|line| {
-										let tokens: Vec<&str> = line.split(",")
-											.collect();
-
-										if tokens.len() != 4 {
-											return;
-										}
-
-										if let (Ok(date), Ok(time)) = (NaiveDate::parse_from_str(tokens[2], "%Y-%m-%d"), NaiveTime::parse_from_str(tokens[3], "%H:%M"))
-										{
-											if let MappedLocalTime::Single(due_date) = NaiveDateTime::new(date, time).and_local_timezone(Local) {
-												let classname = tokens[0];
-												let name = tokens[1];
-												let assign = Assignment{
-													due_date,
-													name: name.to_string(),
-												};
-												let uid = {
-													let mut h = DefaultHasher::new();
-													assign.hash(&mut h);
-													h.finish()
-												};
-												if assignment_by_uid.contains_key(&uid) {
-													return;
-												}
-												assignment_by_uid.insert(uid, assign);
-												completed_by_uid.insert(uid, false);
-												ghost_uids.push(uid);
-												if let Some(uids) = uids_by_class.get_mut(classname) {
-													uids.push(uid);
-												}
-											}
-										}
-									});
+				if let Ok(lines) = String::from_utf8(output.stdout) {
+					lines.lines()
+						.for_each(|line| {
+							if let Some(id) = self.add_script_line(&script, line) {
+								seen.insert((script.clone(), id));
 							}
-						}
+						});
+				}
+			});
+
+		// anything a script stopped reporting is flagged, but kept so the user can decide
+		self.removed_uids = self.assignment_by_uid.iter()
+			.filter_map(|(uid, assign)| {
+				match &assign.source {
+					Some(source) if source.script != CALDAV_SOURCE &&
+						!failed_scripts.contains(&source.script) &&
+						!seen.contains(&(source.script.clone(), source.id.clone())) => Some(*uid),
+					_ => None,
+				}
+			}).collect();
+		for uid in &self.removed_uids {
+			let assign = self.assignment_by_uid.get(uid).unwrap();
+			log::info!("\"{}\" was removed upstream by {}", assign.name, assign.source.as_ref().unwrap().script);
+		}
+	}
+
+	// returns the external id of the line if it had one
+	fn add_script_line(&mut self, script: &str, line: &str) -> Option<String> {
+		let tokens: Vec<&str> = line.split(",")
+			.collect();
+
+		if tokens.len() != 4 && tokens.len() != 5 {
+			return None;
+		}
+
+		// no date and no time means no due date
+		let due_date = if tokens[2].is_empty() && tokens[3].is_empty() {
+			None
+		}
+		else {
+			let date = NaiveDate::parse_from_str(tokens[2], "%Y-%m-%d").ok()?;
+			let time = NaiveTime::parse_from_str(tokens[3], "%H:%M").ok()?;
+			let MappedLocalTime::Single(due_date) = NaiveDateTime::new(date, time).and_local_timezone(Local) else {
+				return None;
+			};
+			Some(due_date)
+		};
+
+		let classname = tokens[0];
+		if !self.uids_by_class.contains_key(classname) {
+			log::warn!("Provider script {} names unknown class \"{}\", skipping", script, classname);
+			return None;
+		}
+		let name = tokens[1];
+		let id = tokens.get(4).map(|id| id.to_string());
+		let mut assign = Assignment{
+			due_date,
+			name: name.to_string(),
+			source: id.as_ref().map(|id| ExternalSource{
+				script: script.to_string(),
+				id: id.clone(),
+			}),
+			tags: vec![],
+			id: 0,
+		};
 
-					});
+		if id.is_none() {
+			let uid = Self::hash_assignment(&assign);
+			if self.assignment_by_uid.contains_key(&uid) {
+				return None;
 			}
+			assign.id = self.new_id(uid);
+			self.assignment_by_uid.insert(uid, assign);
+			self.completed_by_uid.insert(uid, false);
+			self.ghost_uids.push(uid);
+			if let Some(uids) = self.uids_by_class.get_mut(classname) {
+				uids.push(uid);
+			}
+			return None;
+		}
 
-			Ok(Self {
-				uids_by_class,
-				assignment_by_uid,
-				completed_by_uid,
-				ghost_uids,
-				list_path: PathBuf::from(load_path.as_ref()),
-			})
+		let source = assign.source.as_ref().unwrap();
+		if let Some(old_uid) = self.find_external(source) {
+			// scripts don't know about tags, they're kept from here
+			assign.tags = self.assignment_by_uid.get(&old_uid).unwrap().tags.clone();
+			if *self.assignment_by_uid.get(&old_uid).unwrap() != assign {
+				log::info!("Updating \"{}\" from {}", assign.name, script);
+				let description = format!("update: {} / {} from {}", classname, assign.name, script);
+				match self.replace_assignment(old_uid, assign) {
+					Ok(_) => self.mark_dirty(description),
+					Err(()) => log::warn!("Not applying {}, it collides with an existing assignment", description),
+				}
+			}
+		}
+		// a completed ghost from before ids existed, adopt it instead of duplicating
+		else if let Some(old_uid) = self.find_unsourced(classname, &assign) {
+			assign.tags = self.assignment_by_uid.get(&old_uid).unwrap().tags.clone();
+			let description = format!("link: {} / {} to {}", classname, assign.name, script);
+			match self.replace_assignment(old_uid, assign) {
+				Ok(_) => self.mark_dirty(description),
+				Err(()) => log::warn!("Not applying {}, it collides with an existing assignment", description),
+			}
 		}
 		else {
-			log::info!("Couldn't read todolist at {}, creating blank one", load_path.as_ref().display());
-			let _ = File::create_new(&load_path);
-			Ok(Self {
+			let uid = Self::hash_assignment(&assign);
+			if !self.assignment_by_uid.contains_key(&uid) {
+				assign.id = self.new_id(uid);
+				self.assignment_by_uid.insert(uid, assign);
+				self.completed_by_uid.insert(uid, false);
+				if let Some(uids) = self.uids_by_class.get_mut(classname) {
+					uids.push(uid);
+				}
+			}
+		}
+
+		id
+	}
+
+	fn find_external(&self, source: &ExternalSource) -> Option<u64> {
+		self.assignment_by_uid.iter()
+			.find(|(_uid, assign)| assign.source.as_ref() == Some(source))
+			.map(|(uid, _assign)| *uid)
+	}
+
+	fn find_unsourced(&self, classname: &str, assignment: &Assignment) -> Option<u64> {
+		self.uids_by_class.get(classname)?
+			.iter()
+			.find(|uid| {
+				let assign = self.assignment_by_uid.get(uid).unwrap();
+				assign.source.is_none() &&
+					assign.name == assignment.name &&
+					assign.due_date == assignment.due_date
+			})
+			.copied()
+	}
+
+	// swaps the assignment behind old_uid for a new one, keeping its classes, completion and id
+	fn replace_assignment(&mut self, old_uid: u64, mut assignment: Assignment) -> Result<u64, ()> {
+		let uid = Self::hash_assignment(&assignment);
+		if uid == old_uid {
+			return Ok(uid);
+		}
+		if self.assignment_by_uid.contains_key(&uid) {
+			return Err(());
+		}
+
+		let old = self.assignment_by_uid.remove(&old_uid).ok_or(())?;
+		assignment.id = old.id;
+		self.assignment_by_uid.insert(uid, assignment);
+		let completed = self.completed_by_uid.remove(&old_uid).unwrap_or(false);
+		self.completed_by_uid.insert(uid, completed);
+		for uids in self.uids_by_class.values_mut() {
+			uids.iter_mut()
+				.filter(|u| **u == old_uid)
+				.for_each(|u| *u = uid);
+		}
+		self.ghost_uids.retain(|u| *u != old_uid);
+		Ok(uid)
+	}
+
+	// the uid of an assignment, which changes with what it says
+	pub fn hash_assignment(assignment: &Assignment) -> u64 {
+		let mut h = DefaultHasher::new();
+		assignment.hash(&mut h);
+		h.finish()
+	}
+
+	// an id for an assignment added with this uid, the uid itself unless an edited assignment has it already
+	fn new_id(&self, uid: u64) -> u64 {
+		let mut id = uid;
+		while id == 0 || self.id_in_use(id) {
+			id = RandomState::new().hash_one(uid);
+		}
+		id
+	}
+
+	fn id_in_use(&self, id: u64) -> bool {
+		self.assignment_by_uid.values()
+			.any(|assign| assign.id == id)
+	}
+
+	// an empty file is an empty list
+	fn parse_str(list_str: &String) -> Result<TodoListParsed, &'static str> {
+		if list_str.trim().is_empty() {
+			Ok(TodoListParsed{
 				uids_by_class: HashMap::new(),
 				assignment_by_uid: HashMap::new(),
 				completed_by_uid: HashMap::new(),
-				ghost_uids: Vec::new(),
-				list_path: PathBuf::from(load_path.as_ref()),
 			})
 		}
+		else if let Ok(v1) = Self::parse_v1(list_str) {
+			Ok(v1)
+		}
+		else if let Ok(v2) = Self::parse_v2(list_str) {
+			Ok(v2)
+		}
+		else {
+			Err("Failed to parse log file, pretending it's blank")
+		}
+	}
+
+	fn snapshot_of(parsed: &TodoListParsed) -> Snapshot {
+		parsed.uids_by_class.iter()
+			.map(|(class, uids)| {
+				(class.clone(), uids.iter()
+					.map(|uid| {
+						let assign = parsed.assignment_by_uid.get(uid).unwrap();
+						(assign.id, (assign.clone(), *parsed.completed_by_uid.get(uid).unwrap()))
+					})
+					.collect())
+			}).collect()
+	}
+
+	// everything that would be saved, which leaves out uncompleted ghosts
+	pub fn snapshot(&self) -> Snapshot {
+		self.uids_by_class.iter()
+			.map(|(class, uids)| {
+				(class.clone(), uids.iter()
+					.filter_map(|uid| {
+						let assign = self.assignment_by_uid.get(uid).unwrap();
+						let completed = *self.completed_by_uid.get(uid).unwrap();
+						let is_ghost = self.ghost_uids.contains(uid);
+						if !is_ghost || completed {
+							Some((assign.id, (assign.clone(), completed)))
+						}
+						else {
+							None
+						}
+					})
+					.collect())
+			}).collect()
+	}
+
+	// replaces the saveable part of the list with snapshot, keeping uncompleted ghosts around
+	// the snapshot comes from outside, so the undo history is dropped
+	fn adopt_snapshot(&mut self, snapshot: Snapshot) {
+		self.clear_history();
+
+		let ghosts: Vec<(String, u64, Assignment)> = self.uids_by_class.iter()
+			.flat_map(|(class, uids)| uids.iter().map(move |uid| (class, uid)))
+			.filter(|(_class, uid)| self.ghost_uids.contains(uid) && !*self.completed_by_uid.get(uid).unwrap())
+			.map(|(class, uid)| (class.clone(), *uid, self.assignment_by_uid.get(uid).unwrap().clone()))
+			.collect();
+
+		self.uids_by_class.clear();
+		self.assignment_by_uid.clear();
+		self.completed_by_uid.clear();
+		for (class, assignments) in snapshot {
+			let mut uids: Vec<(u64, Assignment)> = vec![];
+			for (assign, completed) in assignments.into_values() {
+				let uid = Self::hash_assignment(&assign);
+				// both sides added the same thing, under their own ids
+				if uids.iter().any(|(u, _)| *u == uid) {
+					continue;
+				}
+				self.completed_by_uid.insert(uid, completed);
+				uids.push((uid, assign));
+			}
+			uids.sort_by(|(_, a), (_, b)| a.cmp(b));
+			self.uids_by_class.insert(class, uids.iter().map(|(uid, _)| *uid).collect());
+			for (uid, assign) in uids {
+				self.assignment_by_uid.insert(uid, assign);
+			}
+		}
+
+		for (class, uid, assign) in ghosts {
+			if let Some(uids) = self.uids_by_class.get_mut(&class) {
+				if let Entry::Vacant(entry) = self.assignment_by_uid.entry(uid) {
+					uids.push(uid);
+					entry.insert(assign);
+					self.completed_by_uid.insert(uid, false);
+				}
+			}
+		}
+		let assignment_by_uid = &self.assignment_by_uid;
+		self.ghost_uids.retain(|uid| assignment_by_uid.contains_key(uid));
+		self.removed_uids.retain(|uid| assignment_by_uid.contains_key(uid));
+	}
+
+	fn hash_str(string: &str) -> u64 {
+		let mut h = DefaultHasher::new();
+		string.hash(&mut h);
+		h.finish()
+	}
+
+	// an advisory lock on "<list>.lock", held for as long as the returned file is
+	// the list file itself gets renamed over when saving, so it can't hold the lock
+	fn lock_list(list_path: &Path, exclusive: bool) -> io::Result<File> {
+		let lock_path = {
+			let mut lock_path = list_path.as_os_str().to_owned();
+			lock_path.push(".lock");
+			PathBuf::from(lock_path)
+		};
+		let lock = File::options()
+			.create(true)
+			.truncate(false)
+			.write(true)
+			.open(lock_path)?;
+		if exclusive {
+			lock.lock()?;
+		}
+		else {
+			lock.lock_shared()?;
+		}
+		Ok(lock)
 	}
 
 	fn parse_v1(list_str: &String) -> Result<TodoListParsed, &'static str> {
@@ -164,8 +521,11 @@ impl TodoList {
 					};
 					let completed = assign.completed;
 					let assign = Assignment{
-						due_date: assign.due_date,
+						due_date: Some(assign.due_date),
 						name: assign.name,
+						source: None,
+						tags: vec![],
+						id: uid,
 					};
 					assignment_by_uid.insert(uid, assign);
 					completed_by_uid.insert(uid, completed);
@@ -188,14 +548,20 @@ impl TodoList {
 			let mut uids_by_class = HashMap::new();
 			let mut assignment_by_uid = HashMap::new();
 			let mut completed_by_uid = HashMap::new();
+			let mut ids = HashSet::new();
 			for (class, assignments) in todo_list_file.assignments {
 				uids_by_class.insert(class.clone(), vec![]);
-				for (assign, completed) in assignments {
+				for (mut assign, completed) in assignments {
 					let uid = {
 						let mut h = DefaultHasher::new();
 						assign.hash(&mut h);
 						h.finish()
 					};
+					// from before ids existed, or copied by hand
+					if assign.id == 0 || !ids.insert(assign.id) {
+						assign.id = uid;
+						ids.insert(uid);
+					}
 					assignment_by_uid.insert(uid, assign);
 					completed_by_uid.insert(uid, completed);
 					uids_by_class.get_mut(&class).unwrap().push(uid);
@@ -213,6 +579,138 @@ impl TodoList {
 	}
 
 	pub fn create_class(&mut self, classname: String) -> Result<(), ()> {
+		self.insert_class(classname.clone())?;
+		self.mark_dirty(format!("add class: {}", classname));
+		self.history.record(Change::CreateClass{
+			classname,
+		});
+		Ok(())
+	}
+
+	pub fn delete_class(&mut self, classname: String) -> Result<(), ()> {
+		let assignments = self.remove_class(&classname)?;
+		self.mark_dirty(format!("delete class: {}", classname));
+		self.history.record(Change::DeleteClass{
+			classname,
+			assignments,
+		});
+		Ok(())
+	}
+
+	pub fn create_assignment(&mut self, classname: String, assignment: Assignment) -> Result<u64, ()> {
+		let uid = self.insert_assignment(&classname, assignment, false)?;
+		// with the id it got, so redoing it brings back the same assignment
+		let assignment = self.assignment_by_uid.get(&uid).unwrap().clone();
+		self.mark_dirty(format!("add: {} / {}", classname, assignment.name));
+		self.history.record(Change::CreateAssignment{
+			classname,
+			assignment,
+		});
+		Ok(uid)
+	}
+
+	// replaces the assignment behind uid, returning its new uid
+	pub fn edit_assignment(&mut self, uid: u64, assignment: Assignment) -> Result<u64, ()> {
+		let before = self.assignment_by_uid.get(&uid).ok_or(())?.clone();
+		let new_uid = self.replace_assignment(uid, assignment.clone())?;
+		if new_uid != uid {
+			self.mark_dirty(format!("edit: {} / {}", self.get_assignment_class(new_uid).unwrap_or_default(), before.name));
+			self.history.record(Change::EditAssignment{
+				before,
+				after: assignment,
+			});
+		}
+		Ok(new_uid)
+	}
+
+	pub fn delete_assignment(&mut self, uid: u64) -> Result<(), ()> {
+		let classname = self.get_assignment_class(uid).ok_or(())?;
+		let assignment = self.assignment_by_uid.get(&uid).ok_or(())?.clone();
+		let completed = *self.completed_by_uid.get(&uid).ok_or(())?;
+		self.remove_assignment(&classname, uid)?;
+		self.mark_dirty(format!("delete: {} / {}", classname, assignment.name));
+		self.history.record(Change::DeleteAssignment{
+			classname,
+			assignment,
+			completed,
+		});
+		Ok(())
+	}
+
+	// marks an assignment as managed elsewhere, returning its new uid
+	// this isn't something to undo, so it stays out of the history
+	pub fn set_assignment_source(&mut self, uid: u64, source: Option<ExternalSource>) -> Result<u64, ()> {
+		let mut assignment = self.assignment_by_uid.get(&uid).ok_or(())?.clone();
+		let description = format!("link: {} / {} to {}",
+			self.get_assignment_class(uid).unwrap_or_default(),
+			assignment.name,
+			source.as_ref().map_or("nothing", |source| source.script.as_str()));
+		assignment.source = source;
+		let new_uid = self.replace_assignment(uid, assignment)?;
+		if new_uid != uid {
+			self.mark_dirty(description);
+		}
+		Ok(new_uid)
+	}
+
+	// reverts the last change, returning what it was
+	pub fn undo(&mut self) -> Result<Change, ()> {
+		let change = self.history.pop_undo().ok_or(())?;
+		// it stays undoable if it can't be undone right now
+		if let Err(()) = self.revert_change(&change) {
+			self.history.push_undo(change);
+			return Err(());
+		}
+		self.history.push_redo(change.clone());
+		self.mark_dirty(format!("undo: {}", change.describe()));
+		Ok(change)
+	}
+
+	// reapplies the last undone change, returning what it was
+	pub fn redo(&mut self) -> Result<Change, ()> {
+		let change = self.history.pop_redo().ok_or(())?;
+		if let Err(()) = self.apply_change(&change) {
+			self.history.push_redo(change);
+			return Err(());
+		}
+		self.history.push_undo(change.clone());
+		self.mark_dirty(format!("redo: {}", change.describe()));
+		Ok(change)
+	}
+
+	fn apply_change(&mut self, change: &Change) -> Result<(), ()> {
+		match change {
+			Change::CreateClass { classname } => self.insert_class(classname.clone()),
+			Change::DeleteClass { classname, .. } => self.remove_class(classname).map(|_| ()),
+			Change::CreateAssignment { classname, assignment } => self.insert_assignment(classname, assignment.clone(), false).map(|_| ()),
+			Change::SetCompletion { assignment, completed } => self.set_completion(Self::hash_assignment(assignment), *completed),
+			Change::EditAssignment { before, after } => self.replace_assignment(Self::hash_assignment(before), after.clone()).map(|_| ()),
+			Change::DeleteAssignment { classname, assignment, .. } => self.remove_assignment(classname, Self::hash_assignment(assignment)),
+		}
+	}
+
+	fn revert_change(&mut self, change: &Change) -> Result<(), ()> {
+		match change {
+			Change::CreateClass { classname } => self.remove_class(classname).map(|_| ()),
+			Change::DeleteClass { classname, assignments } => {
+				self.insert_class(classname.clone())?;
+				for (assignment, completed) in assignments {
+					// all or nothing, a half restored class would be a change of its own
+					if let Err(()) = self.insert_assignment(classname, assignment.clone(), *completed) {
+						let _ = self.remove_class(classname);
+						return Err(());
+					}
+				}
+				Ok(())
+			},
+			Change::CreateAssignment { classname, assignment } => self.remove_assignment(classname, Self::hash_assignment(assignment)),
+			Change::SetCompletion { assignment, completed } => self.set_completion(Self::hash_assignment(assignment), !*completed),
+			Change::EditAssignment { before, after } => self.replace_assignment(Self::hash_assignment(after), before.clone()).map(|_| ()),
+			Change::DeleteAssignment { classname, assignment, completed } => self.insert_assignment(classname, assignment.clone(), *completed).map(|_| ()),
+		}
+	}
+
+	fn insert_class(&mut self, classname: String) -> Result<(), ()> {
 		if !self.uids_by_class.contains_key(&classname) {
 			self.uids_by_class.insert(classname, vec![]);
 			Ok(())
@@ -222,45 +720,41 @@ impl TodoList {
 		}
 	}
 
-	pub fn delete_class(&mut self, classname: String) -> Result<(), ()> {
-		if self.uids_by_class.contains_key(&classname) {
-			let uids = self.uids_by_class.get(&classname).unwrap();
+	// returns the class' assignments and whether they were completed, to put them back later
+	fn remove_class(&mut self, classname: &String) -> Result<Vec<(Assignment, bool)>, ()> {
+		let uids = self.uids_by_class.remove(classname).ok_or(())?;
+
+		let mut assignments = vec![];
+		for uid in uids {
+			assignments.push((self.assignment_by_uid.get(&uid).unwrap().clone(),
+				*self.completed_by_uid.get(&uid).unwrap()));
 
 			// check if other classes have this assign, else remove
-			for uid in uids {
-				let remaining = self.uids_by_class.get(&classname).unwrap()
-					.iter()
-					.find(|u| *u == uid)
-					.is_some();
-				if !remaining {
-					self.assignment_by_uid.remove(uid);
-				}
+			let remaining = self.uids_by_class.values()
+				.any(|uids| uids.contains(&uid));
+			if !remaining {
+				self.forget_assignment(uid);
 			}
-
-			self.uids_by_class.remove(&classname);
-			Ok(())
-		}
-		else {
-			Err(())
 		}
+
+		Ok(assignments)
 	}
 
-	pub fn create_assignment(&mut self, classname: String, assignment: Assignment) -> Result<u64, ()> {
-		match self.uids_by_class.get_mut(&classname) {
+	// a new assignment gets an id here, one put back by undo keeps its own if it's still free
+	fn insert_assignment(&mut self, classname: &String, mut assignment: Assignment, completed: bool) -> Result<u64, ()> {
+		let uid = Self::hash_assignment(&assignment);
+		if assignment.id == 0 || self.id_in_use(assignment.id) {
+			assignment.id = self.new_id(uid);
+		}
+		match self.uids_by_class.get_mut(classname) {
 			Some(class) => {
-				let uid = {
-					let mut h = DefaultHasher::new();
-					assignment.hash(&mut h);
-					h.finish()
-				};
-
 				if self.assignment_by_uid.contains_key(&uid) {
 					Err(())
 				}
 				else {
 					class.push(uid);
 					self.assignment_by_uid.insert(uid, assignment);
-					self.completed_by_uid.insert(uid, false);
+					self.completed_by_uid.insert(uid, completed);
 					Ok(uid)
 				}
 			},
@@ -268,6 +762,28 @@ impl TodoList {
 		}
 	}
 
+	fn remove_assignment(&mut self, classname: &String, uid: u64) -> Result<(), ()> {
+		let class = self.uids_by_class.get_mut(classname).ok_or(())?;
+		if !class.contains(&uid) {
+			return Err(());
+		}
+		class.retain(|u| *u != uid);
+
+		let remaining = self.uids_by_class.values()
+			.any(|uids| uids.contains(&uid));
+		if !remaining {
+			self.forget_assignment(uid);
+		}
+		Ok(())
+	}
+
+	fn forget_assignment(&mut self, uid: u64) {
+		self.assignment_by_uid.remove(&uid);
+		self.completed_by_uid.remove(&uid);
+		self.ghost_uids.retain(|u| *u != uid);
+		self.removed_uids.retain(|u| *u != uid);
+	}
+
 	pub fn get_classes(&self) -> Vec<String> {
 		let mut classes: Vec<String> = self.uids_by_class.iter()
 			.map(|(class, _uids)| class.clone())
@@ -289,27 +805,79 @@ impl TodoList {
 		}
 	}
 
+	pub fn get_class_uids(&self, classname: &str) -> Result<Vec<u64>, ()> {
+		self.uids_by_class.get(classname)
+			.cloned()
+			.ok_or(())
+	}
+
+	// the earliest due assignment in a class with the given name
+	pub fn find_assignment(&self, classname: &str, name: &str) -> Option<u64> {
+		self.uids_by_class.get(classname)?
+			.iter()
+			.filter(|uid| self.assignment_by_uid.get(uid).unwrap().name == name)
+			.min_by_key(|uid| self.assignment_by_uid.get(uid).unwrap())
+			.copied()
+	}
+
+	pub fn get_assignment(&self, uid: u64) -> Result<Assignment, ()> {
+		self.assignment_by_uid.get(&uid)
+			.cloned()
+			.ok_or(())
+	}
+
 	pub fn get_timespan_assignments(&self, start_date: NaiveDate, end_date: NaiveDate) -> HashMap<String, Vec<Assignment>> {
+		self.assignments_where(|_uid, assign| {
+			assign.due_date.is_some_and(|due_date| {
+				let naive = due_date.date_naive();
+				start_date <= naive &&
+					end_date >= naive
+			})
+		})
+	}
+
+	// every uncompleted assignment that was due before now, however long ago
+	pub fn get_overdue_assignments(&self, now: DateTime<Local>) -> HashMap<String, Vec<Assignment>> {
+		self.assignments_where(|uid, assign| {
+			assign.is_past(now) && !self.completed_by_uid.get(&uid).unwrap()
+		})
+	}
+
+	pub fn get_undated_assignments(&self) -> HashMap<String, Vec<Assignment>> {
+		self.assignments_where(|_uid, assign| assign.due_date.is_none())
+	}
+
+	// every class, with the assignments in it that pass
+	fn assignments_where<F: Fn(u64, &Assignment) -> bool>(&self, pass: F) -> HashMap<String, Vec<Assignment>> {
 		self.uids_by_class
 			.iter()
 			.map(|(class, uids)| {
 				(class.clone(), uids
-					.clone()
 					.iter()
-					.map(|uid| self.assignment_by_uid.get(uid).unwrap())
-					.filter(|assign| {
-						let naive = assign.due_date.date_naive();
-						start_date <= naive &&
-							end_date >= naive
-						//let offset = (assign.due_date.date_naive() - from_date).num_seconds();
-						//offset >= 0 && offset < 60 * 60 * 24 * 7
-						// also include 2 day old assignments
-					}).map(|assign| assign.clone())
+					.map(|uid| (*uid, self.assignment_by_uid.get(uid).unwrap()))
+					.filter(|(uid, assign)| pass(*uid, assign))
+					.map(|(_uid, assign)| assign.clone())
 					.collect())
 			}).collect()
 	}
 
 	pub fn set_assignment_completion(&mut self, uid: u64, completed: bool) -> Result<(), ()> {
+		if self.get_assignment_completion(uid)? != completed {
+			self.set_completion(uid, completed)?;
+			let assignment = self.assignment_by_uid.get(&uid).unwrap().clone();
+			self.mark_dirty(format!("{}: {} / {}",
+				if completed { "complete" } else { "uncomplete" },
+				self.get_assignment_class(uid).unwrap_or_default(),
+				assignment.name));
+			self.history.record(Change::SetCompletion{
+				assignment,
+				completed,
+			});
+		}
+		Ok(())
+	}
+
+	fn set_completion(&mut self, uid: u64, completed: bool) -> Result<(), ()> {
 		match self.completed_by_uid.get_mut(&uid) {
 			Some(comp) => {
 				*comp = completed;
@@ -319,6 +887,15 @@ impl TodoList {
 		}
 	}
 
+	// what undo and redo would do next, if anything
+	pub fn peek_undo(&self) -> Option<&Change> {
+		self.history.peek_undo()
+	}
+
+	pub fn peek_redo(&self) -> Option<&Change> {
+		self.history.peek_redo()
+	}
+
 	pub fn get_assignment_completion(&self, uid: u64) -> Result<bool, ()> {
 		match self.completed_by_uid.get(&uid) {
 			Some (comp) => {
@@ -328,31 +905,220 @@ impl TodoList {
 		}
 	}
 
-	pub fn save_to_file(&self) {
+	// whether this externally managed assignment is no longer reported by its script
+	pub fn is_removed_upstream(&self, uid: u64) -> bool {
+		self.removed_uids.contains(&uid)
+	}
+
+	fn mark_dirty(&mut self, description: String) {
+		self.dirty = true;
+		self.changed_at = Some(Instant::now());
+		self.unsaved_changes.push(description);
+	}
+
+	pub fn get_assignment_class(&self, uid: u64) -> Option<String> {
+		self.uids_by_class.iter()
+			.find(|(_class, uids)| uids.contains(&uid))
+			.map(|(class, _uids)| class.clone())
+	}
+
+	pub fn is_dirty(&self) -> bool {
+		self.dirty
+	}
+
+	// how long ago the last unsaved change was made
+	pub fn dirty_for(&self) -> Option<Duration> {
+		if self.dirty {
+			self.changed_at.map(|changed_at| changed_at.elapsed())
+		}
+		else {
+			None
+		}
+	}
+
+	pub fn get_list_path(&self) -> &Path {
+		&self.list_path
+	}
+
+	// picks up changes someone else saved to the list file
+	// only done when there's nothing unsaved here, otherwise the next save merges them in
+	// returns whether anything changed
+	pub fn reload(&mut self) -> Result<bool, &'static str> {
+		if self.dirty || self.pending_merge.is_some() {
+			return Ok(false);
+		}
+
+		let list_str = {
+			let _lock = Self::lock_list(&self.list_path, false);
+			if !self.list_path.is_file() {
+				return Err("List file went missing");
+			}
+			TodoList::read_file_sans_newline(&self.list_path)
+		};
+		let list_hash = Self::hash_str(&list_str);
+		// most likely our own save
+		if list_hash == self.base_hash {
+			return Ok(false);
+		}
+
+		let theirs = Self::snapshot_of(&Self::parse_str(&list_str)?);
+		self.adopt_snapshot(theirs.clone());
+		self.base = theirs;
+		self.base_hash = list_hash;
+		Ok(true)
+	}
+
+	// saves the list, merging in whatever someone else saved to the file since we last did
+	// returns whether anything had to be merged in, which means the views need refreshing
+	pub fn save_to_file(&mut self) -> Result<bool, SaveError> {
 		log::info!("Saving todolist to file...");
-		//if self.list_dir.try_exists().unwrap() {
-			//fs::remove_dir_all(self.list_dir.as_path()).unwrap();
-		//}
 
+		let _lock = Self::lock_list(&self.list_path, true)?;
+
+		let mut merged = false;
+		let disk_str = if self.list_path.exists() {
+			TodoList::read_file_sans_newline(&self.list_path)
+		}
+		else {
+			String::new()
+		};
+		let disk_hash = Self::hash_str(&disk_str);
+		if disk_hash != self.base_hash {
+			match Self::parse_str(&disk_str) {
+				Ok(parsed) => {
+					log::info!("List file changed since it was loaded, merging");
+					let theirs = Self::snapshot_of(&parsed);
+					let (snapshot, conflicts) = merge(&self.base, &self.snapshot(), &theirs);
+					if !conflicts.is_empty() {
+						let n = conflicts.len();
+						self.pending_merge = Some(PendingMerge {
+							merged: snapshot,
+							theirs,
+							theirs_hash: disk_hash,
+							resolutions: vec![None; n],
+							conflicts,
+						});
+						return Err(SaveError::Conflicts(n));
+					}
+					self.adopt_snapshot(snapshot);
+					self.unsaved_changes.push("merge: changes saved elsewhere".to_string());
+					merged = true;
+				},
+				Err(e) => log::warn!("{}, overwriting it", e),
+			}
+		}
+
+		let snapshot = self.snapshot();
+		let json = Self::serialize_snapshot(&snapshot);
+		TodoList::write_str_to_file(&self.list_path, json.clone())?;
+		self.base = snapshot;
+		self.base_hash = Self::hash_str(&json);
+		self.pending_merge = None;
+		self.dirty = false;
+
+		let changes = std::mem::take(&mut self.unsaved_changes);
+		if config().git.enabled {
+			git::queue_commit(&self.list_path, changes, &config().git);
+		}
+
+		if config().history.persist {
+			self.history.save(&self.history_path());
+		}
+		Ok(merged)
+	}
+
+	fn serialize_snapshot(snapshot: &Snapshot) -> String {
 		let mut serialize = TodoListV2{
 			version: 2,
 			assignments: BTreeMap::<_, _>::new(),
 		};
-		for (class, uids) in &self.uids_by_class {
-			let mut assignments = vec![];
-			for uid in uids {
-				let assign = self.assignment_by_uid.get(&uid).unwrap();
-				let completed = *self.completed_by_uid.get(&uid).unwrap();
-				let is_ghost = self.ghost_uids.contains(uid);
-				if !is_ghost || (is_ghost && completed) {
-					assignments.push((assign.clone(), completed));
-				}
-			}
+		for (class, assignments) in snapshot {
+			let mut assignments: Vec<(Assignment, bool)> = assignments.values()
+				.cloned()
+				.collect();
+			assignments.sort();
 			serialize.assignments.insert(class.clone(), assignments);
 		}
 
-		let json = serde_json::to_string_pretty(&serialize).unwrap();
-		TodoList::write_str_to_file(&self.list_path, json);
+		serde_json::to_string_pretty(&serialize).unwrap()
+	}
+
+	// the git merge driver: merges the list files theirs and ours changed from base into ours
+	// conflicts keep our side, and are returned so they can be reported
+	pub fn merge_files(base: &Path, ours: &Path, theirs: &Path) -> Result<Vec<Conflict>, &'static str> {
+		let read = |path: &Path| -> Result<Snapshot, &'static str> {
+			let list_str = fs::read_to_string(path)
+				.map_err(|_| "Couldn't read a list file to merge")?;
+			Ok(Self::snapshot_of(&Self::parse_str(&list_str)?))
+		};
+		let (mut merged, conflicts) = merge(&read(base)?, &read(ours)?, &read(theirs)?);
+		for conflict in &conflicts {
+			apply_resolution(&mut merged, conflict, Resolution::Ours);
+		}
+
+		TodoList::write_str_to_file(ours, Self::serialize_snapshot(&merged))
+			.map_err(|_| "Couldn't write the merged list file")?;
+		Ok(conflicts)
+	}
+
+	// conflicts from the last save that need the user to pick a side
+	pub fn get_conflicts(&self) -> Vec<Conflict> {
+		match &self.pending_merge {
+			Some(pending) => pending.conflicts.clone(),
+			None => vec![],
+		}
+	}
+
+	pub fn resolve_conflict(&mut self, index: usize, resolution: Resolution) -> Result<(), ()> {
+		let pending = self.pending_merge.as_mut().ok_or(())?;
+		*pending.resolutions.get_mut(index).ok_or(())? = Some(resolution);
+		Ok(())
+	}
+
+	// takes in the merge once every conflict is resolved, then saves it
+	pub fn finish_merge(&mut self) -> Result<bool, SaveError> {
+		let pending = match self.pending_merge.take() {
+			Some(pending) if pending.resolutions.iter().all(|r| r.is_some()) => pending,
+			Some(pending) => {
+				let n = pending.conflicts.len();
+				self.pending_merge = Some(pending);
+				return Err(SaveError::Conflicts(n));
+			},
+			None => return self.save_to_file(),
+		};
+
+		let mut merged = pending.merged;
+		for (conflict, resolution) in pending.conflicts.iter().zip(pending.resolutions) {
+			apply_resolution(&mut merged, conflict, resolution.unwrap());
+		}
+		self.adopt_snapshot(merged);
+		self.unsaved_changes.push("merge: changes saved elsewhere".to_string());
+		// the merge already has their changes, so they're the new base
+		self.base = pending.theirs;
+		self.base_hash = pending.theirs_hash;
+		self.save_to_file()
+			.map(|_| true)
+	}
+
+	// throws away the merge and keeps the list as it is, to be saved again later
+	pub fn abort_merge(&mut self) {
+		self.pending_merge = None;
+	}
+
+	// forgets every change, in the saved history too
+	// undoing past a change made elsewhere could bring back what it replaced, or fail halfway
+	pub fn clear_history(&mut self) {
+		self.history.clear();
+		if config().history.persist {
+			self.history.save(&self.history_path());
+		}
+	}
+
+	// the undo history is kept next to the list, if it's kept at all
+	fn history_path(&self) -> PathBuf {
+		let mut path = self.list_path.as_os_str().to_owned();
+		path.push(".history");
+		PathBuf::from(path)
 	}
 
 	fn read_file_sans_newline<P>(file_path: P) -> String
@@ -372,22 +1138,28 @@ impl TodoList {
 		file_content
 	}
 
-	fn write_str_to_file<P>(file_path: P, string: String)
+	// writes to a temporary file first and renames it over the target,
+	// so the file is never left half-written
+	fn write_str_to_file<P>(file_path: P, string: String) -> io::Result<()>
 		where P: AsRef<Path>
 	{
-		let file = File::create(file_path)
-			.unwrap();
+		let tmp_path = {
+			let mut tmp_path = file_path.as_ref().as_os_str().to_owned();
+			tmp_path.push(".tmp");
+			PathBuf::from(tmp_path)
+		};
+
+		let file = File::create(&tmp_path)?;
 		let mut writer = BufWriter::new(file);
-		let _len = writer.write(string.as_bytes()).unwrap();
+		writer.write_all(string.as_bytes())?;
 		if !string.ends_with('\n') {
-			let _len = writer.write("\n".as_bytes()).unwrap();
+			writer.write_all("\n".as_bytes())?;
 		}
-	}
-}
+		writer.into_inner()
+			.map_err(|e| e.into_error())?
+			.sync_all()?;
 
-impl Drop for TodoList {
-	fn drop(&mut self) {
-		self.save_to_file();
+		fs::rename(&tmp_path, file_path)
 	}
 }
 
@@ -398,6 +1170,14 @@ impl Clone for TodoList {
 			assignment_by_uid: self.assignment_by_uid.clone(),
 			completed_by_uid: self.completed_by_uid.clone(),
 			ghost_uids: self.ghost_uids.clone(),
+			removed_uids: self.removed_uids.clone(),
+			history: self.history.clone(),
+			dirty: self.dirty,
+			changed_at: self.changed_at,
+			unsaved_changes: self.unsaved_changes.clone(),
+			base: self.base.clone(),
+			base_hash: self.base_hash,
+			pending_merge: self.pending_merge.clone(),
 			list_path: self.list_path.clone(),
 		}
 	}
@@ -409,6 +1189,9 @@ impl PartialEq for TodoList {
 			self.assignment_by_uid == other.assignment_by_uid &&
 			self.completed_by_uid == other.completed_by_uid &&
 			self.ghost_uids == other.ghost_uids &&
+			self.removed_uids == other.removed_uids &&
+			self.history == other.history &&
+			self.dirty == other.dirty &&
 			self.list_path == other.list_path
 	}
 }
@@ -0,0 +1,121 @@
+/*
+Copyright (C) 2024 Ryan Rhee
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, see
+<https://www.gnu.org/licenses/>.
+*/
+
+#[cfg(target_os = "linux")]
+use inotify::{Inotify, WatchDescriptor, WatchMask, Watches};
+#[cfg(target_os = "linux")]
+use std::ffi::OsString;
+#[cfg(target_os = "linux")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(target_os = "linux")]
+use std::thread;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use log;
+
+// the list's directory gets watched rather than the list itself,
+// since saving renames a new file over the list
+#[cfg(target_os = "linux")]
+struct Watched {
+	watches: Watches,
+	descriptor: Option<WatchDescriptor>,
+	file_name: Option<OsString>,
+}
+
+#[cfg(target_os = "linux")]
+static WATCHED: OnceLock<Mutex<Watched>> = OnceLock::new();
+
+// calls on_change from another thread whenever the watched list file is written or replaced
+// that includes our own saves, so on_change has to work out whether anything really changed
+#[cfg(target_os = "linux")]
+pub fn start_watching<F>(on_change: F) -> Result<(), String>
+	where F: Fn() + Send + 'static
+{
+	let mut inotify = Inotify::init()
+		.map_err(|e| format!("Couldn't start inotify: {}", e))?;
+	let watched = Watched {
+		watches: inotify.watches(),
+		descriptor: None,
+		file_name: None,
+	};
+	if WATCHED.set(Mutex::new(watched)).is_err() {
+		return Err("Already watching the list file".to_string());
+	}
+
+	thread::spawn(move || {
+		let mut buffer = [0; 4096];
+		loop {
+			let events = match inotify.read_events_blocking(&mut buffer) {
+				Ok(events) => events,
+				Err(e) => {
+					log::error!("Stopped watching the list file: {}", e);
+					break;
+				},
+			};
+
+			let changed = {
+				let watched = WATCHED.get().unwrap().lock().unwrap();
+				events.into_iter()
+					.filter(|event| Some(&event.wd) == watched.descriptor.as_ref())
+					.any(|event| event.name.is_some() && event.name == watched.file_name.as_deref())
+			};
+			if changed {
+				on_change();
+			}
+		}
+	});
+
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start_watching<F>(_on_change: F) -> Result<(), String>
+	where F: Fn() + Send + 'static
+{
+	Err("Watching the list file is only supported on Linux".to_string())
+}
+
+// switches which list file is being watched, for when the profile changes
+#[cfg(target_os = "linux")]
+pub fn watch_list(list_path: &Path) -> Result<(), String> {
+	let Some(watched) = WATCHED.get() else {
+		return Ok(());
+	};
+	let mut watched = watched.lock().unwrap();
+
+	if let Some(descriptor) = watched.descriptor.take() {
+		let _ = watched.watches.remove(descriptor);
+	}
+
+	let dir = match list_path.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	};
+	let descriptor = watched.watches.add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+		.map_err(|e| format!("Couldn't watch {}: {}", dir.display(), e))?;
+	watched.descriptor = Some(descriptor);
+	watched.file_name = list_path.file_name().map(|name| name.to_os_string());
+	log::info!("Watching {} for changes", list_path.display());
+
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch_list(_list_path: &Path) -> Result<(), String> {
+	Ok(())
+}